@@ -0,0 +1,43 @@
+use crate::{models::unix_timestamp, DBType, Error};
+use sqlx::{Any, AnyPool};
+use std::time::{Duration, SystemTime};
+
+/// The jobs of a queue that are available but not reserved yet.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Backlog {
+    pub(crate) size: u64,
+    /// How long the oldest of them has been available.
+    pub(crate) oldest_age: Duration,
+}
+
+pub(crate) async fn measure(
+    pool: &AnyPool,
+    db_type: DBType,
+    queue: &str,
+) -> Result<Backlog, Error> {
+    let time = unix_timestamp(SystemTime::now())?;
+
+    let (size, oldest) = sqlx::query_as::<Any, (i64, Option<i64>)>(&format!(
+        r#"
+        SELECT COUNT(*), MIN(available_at)
+        FROM jobs
+        WHERE queue = {} AND reserved_at IS NULL AND available_at <= {}
+        "#,
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$1",
+        },
+        time
+    ))
+    .bind(queue)
+    .fetch_one(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(Backlog {
+        size: size.max(0) as u64,
+        oldest_age: oldest
+            .map(|oldest| Duration::from_secs((time - oldest).max(0) as u64))
+            .unwrap_or_default(),
+    })
+}
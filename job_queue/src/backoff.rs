@@ -0,0 +1,212 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Randomization applied to a computed backoff delay.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Jitter {
+    /// Use the computed delay as is.
+    #[default]
+    None,
+    /// Pick a random delay between zero and the computed delay.
+    Full,
+    /// Pick a random delay between the delay of the first attempt and three times
+    /// the delay actually waited before the previous retry.
+    Decorrelated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum Strategy {
+    Constant(Duration),
+    Linear(Duration),
+    Exponential(Duration),
+    Explicit(Vec<Duration>),
+}
+
+/// How long to wait before retrying a failed job.
+///
+/// ```
+/// use job_queue::{Backoff, Jitter};
+/// use std::time::Duration;
+///
+/// let backoff = Backoff::exponential(Duration::from_secs(1))
+///     .jitter(Jitter::Full)
+///     .max(Duration::from_secs(3600));
+///
+/// assert!(backoff.delay(20) <= Duration::from_secs(3600));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Backoff {
+    strategy: Strategy,
+    jitter: Jitter,
+    max: Option<Duration>,
+}
+
+impl Backoff {
+    /// Wait the same delay after every attempt.
+    pub fn constant(delay: Duration) -> Self {
+        Self::new(Strategy::Constant(delay))
+    }
+
+    /// Wait `step * attempt` after every attempt.
+    pub fn linear(step: Duration) -> Self {
+        Self::new(Strategy::Linear(step))
+    }
+
+    /// Wait `base * 2^attempt` after every attempt.
+    pub fn exponential(base: Duration) -> Self {
+        Self::new(Strategy::Exponential(base))
+    }
+
+    /// Wait the given number of seconds after each attempt, reusing the last
+    /// value once the list is exhausted, e.g. `Backoff::explicit([10, 60, 300])`.
+    pub fn explicit(delays: impl IntoIterator<Item = u64>) -> Self {
+        Self::new(Strategy::Explicit(
+            delays.into_iter().map(Duration::from_secs).collect(),
+        ))
+    }
+
+    fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            jitter: Jitter::None,
+            max: None,
+        }
+    }
+
+    /// Randomize the computed delays.
+    pub fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Never wait longer than `max`.
+    pub fn max(mut self, max: Duration) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// The delay before the next try, `attempt` being the number of attempts made so far.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.next_delay(attempt, None)
+    }
+
+    /// Like [`Backoff::delay`], `previous` being the delay waited before the
+    /// previous retry, which [`Jitter::Decorrelated`] grows from.
+    pub fn next_delay(&self, attempt: u32, previous: Option<Duration>) -> Duration {
+        let attempt = attempt.max(1);
+        let cap = self.max.unwrap_or(Duration::MAX);
+
+        match self.jitter {
+            Jitter::None => self.base_delay(attempt).min(cap),
+            Jitter::Full => random_between(Duration::ZERO, self.base_delay(attempt).min(cap)),
+            Jitter::Decorrelated => {
+                let low = self.base_delay(1);
+                let high = previous.unwrap_or(low).max(low).saturating_mul(3);
+
+                random_between(low, high).min(cap)
+            }
+        }
+    }
+
+    fn base_delay(&self, attempt: u32) -> Duration {
+        match &self.strategy {
+            Strategy::Constant(delay) => *delay,
+            Strategy::Linear(step) => step.saturating_mul(attempt),
+            Strategy::Exponential(base) => base.saturating_mul(2u32.saturating_pow(attempt)),
+            Strategy::Explicit(delays) => delays
+                .get(attempt.saturating_sub(1) as usize)
+                .or(delays.last())
+                .copied()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for Backoff {
+    /// `2^attempt` seconds, without jitter or cap.
+    fn default() -> Self {
+        Self::exponential(Duration::from_secs(1))
+    }
+}
+
+fn random_between(low: Duration, high: Duration) -> Duration {
+    let low = low.as_millis().min(u64::MAX as u128) as u64;
+    let high = high.as_millis().min(u64::MAX as u128) as u64;
+
+    if high <= low {
+        return Duration::from_millis(low);
+    }
+
+    Duration::from_millis(rand::thread_rng().gen_range(low..=high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn strategies() {
+        assert_eq!(Backoff::constant(SECOND * 5).delay(3), SECOND * 5);
+        assert_eq!(Backoff::linear(SECOND * 5).delay(3), SECOND * 15);
+        assert_eq!(Backoff::exponential(SECOND).delay(3), SECOND * 8);
+        assert_eq!(Backoff::default().delay(1), SECOND * 2);
+    }
+
+    #[test]
+    fn explicit_delays_reuse_the_last_one() {
+        let backoff = Backoff::explicit([10, 60, 300]);
+
+        assert_eq!(backoff.delay(1), SECOND * 10);
+        assert_eq!(backoff.delay(3), SECOND * 300);
+        assert_eq!(backoff.delay(10), SECOND * 300);
+        assert_eq!(Backoff::explicit([]).delay(1), Duration::ZERO);
+    }
+
+    #[test]
+    fn max_caps_the_delay() {
+        let backoff = Backoff::exponential(SECOND).max(SECOND * 60);
+
+        assert_eq!(backoff.delay(5), SECOND * 32);
+        assert_eq!(backoff.delay(6), SECOND * 60);
+        assert_eq!(backoff.delay(u32::MAX), SECOND * 60);
+    }
+
+    #[test]
+    fn full_jitter_stays_below_the_delay() {
+        let backoff = Backoff::exponential(SECOND).jitter(Jitter::Full);
+
+        for _ in 0..100 {
+            assert!(backoff.delay(3) <= SECOND * 8);
+        }
+    }
+
+    #[test]
+    fn decorrelated_jitter_grows_from_the_previous_delay() {
+        let backoff = Backoff::exponential(SECOND)
+            .jitter(Jitter::Decorrelated)
+            .max(SECOND * 20);
+
+        for _ in 0..100 {
+            let first = backoff.delay(1);
+            let next = backoff.next_delay(2, Some(SECOND * 5));
+
+            assert!(first >= SECOND * 2 && first <= SECOND * 6);
+            assert!(next >= SECOND * 2 && next <= SECOND * 15);
+            assert!(backoff.next_delay(3, Some(SECOND * 60)) <= SECOND * 20);
+        }
+    }
+
+    #[test]
+    fn decorrelated_jitter_ignores_the_attempt() {
+        let backoff = Backoff::constant(SECOND * 10).jitter(Jitter::Decorrelated);
+
+        for _ in 0..100 {
+            let delay = backoff.next_delay(50, Some(SECOND));
+
+            assert!(delay >= SECOND * 10 && delay <= SECOND * 30);
+        }
+    }
+}
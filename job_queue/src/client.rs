@@ -1,4 +1,10 @@
-use crate::{get_pool, models::FailedJob, DBType, Error, Job};
+use crate::{
+    get_pool,
+    job::validate_limits,
+    models::{unix_timestamp, FailedJob, JobOptions},
+    registry, Backoff, DBType, Error, Job, WorkerInfo,
+};
+use serde::Deserialize;
 use sqlx::{Any, AnyPool, Connection};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
@@ -7,6 +13,23 @@ use uuid::Uuid;
 pub struct DispatchOptions {
     pub queue: Option<String>,
     pub delay: Option<Duration>,
+    /// Overrides [`Job::retry_until`].
+    pub retry_until: Option<SystemTime>,
+    /// Overrides [`Job::ttl`], the job is moved to the failed jobs if not started by then.
+    pub expires_at: Option<SystemTime>,
+    /// Overrides [`Job::tries`].
+    pub tries: Option<u32>,
+    /// Overrides [`Job::timeout`].
+    pub timeout: Option<Duration>,
+    /// Overrides [`Job::backoff`].
+    pub backoff: Option<Backoff>,
+    /// Jobs of the same group run one at a time, in the order they were dispatched,
+    /// a job waiting for its retries to run before the next one starts.
+    pub group: Option<String>,
+    /// Recorded as a plain `trace_parent` field on the job's span with the `tracing`
+    /// feature, such as the W3C `traceparent` of the request dispatching the job, to
+    /// find the job from the request. The span isn't parented to it.
+    pub trace_parent: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -20,13 +43,14 @@ impl Client {
         ClientBuilder::new()
     }
 
-    pub async fn dispatch(&self, job: &impl Job) -> Result<(), Error> {
+    /// Dispatch a job on its queue, returns the uuid of the dispatched job.
+    pub async fn dispatch(&self, job: &impl Job) -> Result<String, Error> {
         let queue = job.queue();
 
         self.dispatch_on_queue(job, &queue).await
     }
 
-    pub async fn dispatch_on_queue(&self, job: &impl Job, queue: &str) -> Result<(), Error> {
+    pub async fn dispatch_on_queue(&self, job: &impl Job, queue: &str) -> Result<String, Error> {
         let options = DispatchOptions {
             queue: Some(queue.to_string()),
             ..Default::default()
@@ -39,7 +63,7 @@ impl Client {
         &self,
         job: &impl Job,
         options: &DispatchOptions,
-    ) -> Result<(), Error> {
+    ) -> Result<String, Error> {
         let mut conn = self.pool.clone().acquire().await?;
         let payload = serde_json::to_string(job as &dyn Job).map_err(Error::SerdeError)?;
         let time = SystemTime::now()
@@ -49,16 +73,52 @@ impl Client {
 
         let job_id = Uuid::new_v4().to_string();
         let queue = options.queue.clone().unwrap_or_else(|| job.queue());
+        let job_options = JobOptions {
+            retry_until: options
+                .retry_until
+                .or_else(|| job.retry_until())
+                .map(unix_timestamp)
+                .transpose()?,
+            expires_at: match options.expires_at {
+                Some(expires_at) => Some(unix_timestamp(expires_at)?),
+                None => job.ttl().map(|ttl| (time + ttl.as_secs()) as i64),
+            },
+            tries: options.tries,
+            timeout_ms: options
+                .timeout
+                .map(|timeout| timeout.as_millis().min(u64::MAX as u128) as u64),
+            backoff: options.backoff.clone(),
+            group: options.group.clone(),
+            trace_parent: options.trace_parent.clone(),
+        };
+
+        if job_options.tries.unwrap_or_else(|| job.tries()) == 0 {
+            return Err(Error::InvalidConfiguration(
+                "a job must be tried at least once".to_string(),
+            ));
+        }
+
+        if job_options
+            .timeout()
+            .unwrap_or_else(|| job.timeout())
+            .is_zero()
+        {
+            return Err(Error::InvalidConfiguration(
+                "a job timeout must be greater than zero".to_string(),
+            ));
+        }
+
+        validate_limits(job)?;
 
         sqlx::query(&format!(
-            "INSERT INTO jobs (uuid, queue, payload, attempts, available_at, created_at) VALUES {}",
+            "INSERT INTO jobs (uuid, queue, payload, attempts, available_at, created_at, options, group_key) VALUES {}",
             match self.db_type {
-                DBType::Mysql => "(?, ?, ?, ?, ?, ?)",
-                DBType::Postgres => "($1, $2, $3, $4, $5, $6)",
+                DBType::Mysql => "(?, ?, ?, ?, ?, ?, ?, ?)",
+                DBType::Postgres => "($1, $2, $3, $4, $5, $6, $7, $8)",
             }
         ))
-        .bind(job_id)
-        .bind(queue)
+        .bind(&job_id)
+        .bind(&queue)
         .bind(payload)
         .bind(0)
         .bind(
@@ -69,21 +129,33 @@ impl Client {
                     .as_secs()) as i64,
         )
         .bind(time as i64)
+        .bind(serde_json::to_string(&job_options).map_err(Error::SerdeError)?)
+        .bind(job_options.group)
         .execute(&mut *conn)
         .await
         .map_err(Error::DatabaseError)?;
 
         conn.close().await?;
 
-        Ok(())
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            uuid = %job_id,
+            queue = %queue,
+            job_type = job.typetag_name(),
+            "Job dispatched"
+        );
+
+        Ok(job_id)
     }
 
+    /// Dispatch a failed job again, without the `expires_at` and `retry_until`
+    /// deadlines of its original dispatch.
     pub async fn retry_failed_job(&self, job_id: &str) -> Result<(), Error> {
         let mut pool = self.pool.acquire().await?;
         let mut conn = pool.begin().await?;
 
         let failed_job = sqlx::query_as::<Any, FailedJob>(&format!(
-            "SELECT id, uuid, queue, payload FROM failed_jobs WHERE uuid = {}",
+            "SELECT queue, payload, options FROM failed_jobs WHERE uuid = {}",
             match self.db_type {
                 DBType::Mysql => "?",
                 DBType::Postgres => "$1",
@@ -99,11 +171,22 @@ impl Client {
             .map_err(|_| Error::Unknown)?
             .as_secs() as i64;
 
+        // The deadlines were set for the original dispatch and have usually passed
+        // by now, the retried job gets its tries instead.
+        let options = match &failed_job.options {
+            Some(options) => JobOptions {
+                expires_at: None,
+                retry_until: None,
+                ..JobOptions::deserialize(&options.0).map_err(Error::SerdeError)?
+            },
+            None => JobOptions::default(),
+        };
+
         sqlx::query(&format!(
-            "INSERT INTO jobs (uuid, queue, payload, attempts, available_at, created_at) VALUES {}",
+            "INSERT INTO jobs (uuid, queue, payload, attempts, available_at, created_at, options, group_key) VALUES {}",
             match self.db_type {
-                DBType::Mysql => "(?, ?, ?, ?, ?, ?)",
-                DBType::Postgres => "($1, $2, $3, $4, $5, $6)",
+                DBType::Mysql => "(?, ?, ?, ?, ?, ?, ?, ?)",
+                DBType::Postgres => "($1, $2, $3, $4, $5, $6, $7, $8)",
             }
         ))
         .bind(job_id)
@@ -112,6 +195,8 @@ impl Client {
         .bind(0)
         .bind(time)
         .bind(time)
+        .bind(serde_json::to_string(&options).map_err(Error::SerdeError)?)
+        .bind(options.group)
         .execute(&mut *conn)
         .await
         .map_err(Error::DatabaseError)?;
@@ -186,6 +271,97 @@ impl Client {
         Ok(())
     }
 
+    /// Flag a job as cancelled. The worker running it cancels the job's
+    /// [`JobContext`](crate::JobContext) and moves it to the `cancelled_jobs` table
+    /// once it stops, a job that hasn't started yet is moved there without running.
+    /// Cancelled jobs are kept apart from the failed jobs, so they are never
+    /// retried by [`Client::retry_all_failed_jobs`].
+    pub async fn cancel_job(&self, job_id: &str) -> Result<(), Error> {
+        let mut conn = self.pool.clone().acquire().await?;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Error::Unknown)?
+            .as_secs() as i64;
+
+        sqlx::query(&format!(
+            "UPDATE jobs SET cancelled_at = {} WHERE uuid = {}",
+            match self.db_type {
+                DBType::Mysql => "?",
+                DBType::Postgres => "$1",
+            },
+            match self.db_type {
+                DBType::Mysql => "?",
+                DBType::Postgres => "$2",
+            }
+        ))
+        .bind(time)
+        .bind(job_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        conn.close().await?;
+
+        Ok(())
+    }
+
+    /// Stop all workers from taking new jobs from the queue until it is resumed,
+    /// jobs already running are not affected.
+    pub async fn pause_queue(&self, queue: &str) -> Result<(), Error> {
+        let mut conn = self.pool.clone().acquire().await?;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Error::Unknown)?
+            .as_secs() as i64;
+
+        sqlx::query(match self.db_type {
+            DBType::Mysql => "INSERT IGNORE INTO paused_queues (queue, paused_at) VALUES (?, ?)",
+            DBType::Postgres => {
+                "INSERT INTO paused_queues (queue, paused_at) VALUES ($1, $2) ON CONFLICT DO NOTHING"
+            }
+        })
+        .bind(queue)
+        .bind(time)
+        .execute(&mut *conn)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        conn.close().await?;
+
+        Ok(())
+    }
+
+    pub async fn resume_queue(&self, queue: &str) -> Result<(), Error> {
+        let mut conn = self.pool.clone().acquire().await?;
+
+        sqlx::query(&format!(
+            "DELETE FROM paused_queues WHERE queue = {}",
+            match self.db_type {
+                DBType::Mysql => "?",
+                DBType::Postgres => "$1",
+            }
+        ))
+        .bind(queue)
+        .execute(&mut *conn)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        conn.close().await?;
+
+        Ok(())
+    }
+
+    /// Ask every running worker to finish its current jobs and stop, so the
+    /// process supervisor can start them again, e.g. after a deploy.
+    pub async fn restart_workers(&self) -> Result<(), Error> {
+        registry::signal_restart(&self.pool, self.db_type).await
+    }
+
+    /// The live workers and the jobs each of them is running.
+    pub async fn workers(&self) -> Result<Vec<WorkerInfo>, Error> {
+        registry::list(&self.pool).await
+    }
+
     pub async fn delete_job(&self, job_id: &str) -> Result<(), Error> {
         let mut conn = self.pool.clone().acquire().await?;
 
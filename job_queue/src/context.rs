@@ -0,0 +1,54 @@
+use tokio_util::sync::CancellationToken;
+
+/// Information about the job being processed, passed to [`Job::handle`](crate::Job::handle).
+#[derive(Debug, Clone)]
+pub struct JobContext {
+    pub(crate) id: i64,
+    pub(crate) uuid: String,
+    pub(crate) job_type: String,
+    pub(crate) queue: String,
+    pub(crate) attempt: u32,
+    pub(crate) token: CancellationToken,
+}
+
+impl JobContext {
+    /// The id of the job row.
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// The uuid of the job, as used by [`Client::cancel_job`](crate::Client::cancel_job).
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    /// The typetag name of the job.
+    pub fn job_type(&self) -> &str {
+        &self.job_type
+    }
+
+    /// The queue the job was reserved from.
+    pub fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    /// The current attempt, starting at 1.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Cancelled when the job is cancelled through [`Client::cancel_job`](crate::Client::cancel_job)
+    /// or when the worker is stopped by Ctrl-C or its memory limit.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Wait until the job is cancelled.
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+}
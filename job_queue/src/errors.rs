@@ -8,10 +8,18 @@ pub enum Error {
     UnsupportedDatabaseUrl,
     #[error("serializing error")]
     SerdeError(#[from] serde_json::Error),
+    #[error("invalid configuration: {0}")]
+    InvalidConfiguration(String),
     #[error("job error: {0}")]
     Message(String),
     #[error("job timeout")]
     JobTimeout,
+    #[error("job aborted")]
+    JobAborted,
+    #[error("job cancelled")]
+    JobCancelled,
+    #[error("job expired")]
+    JobExpired,
     #[error("job panic")]
     JobPanic,
     #[error("unknown data store error")]
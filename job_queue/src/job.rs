@@ -1,15 +1,20 @@
-use crate::Error;
+use crate::{Backoff, Error, JobContext, JobMiddleware, RateLimit};
 use async_trait::async_trait;
-use std::panic::RefUnwindSafe;
+use std::{
+    panic::RefUnwindSafe,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 const COMMON_QUEUE: &str = "default";
-const TRIES: i16 = 1;
-const TIMEOUT: i16 = 300;
+const TRIES: u32 = 1;
+const TIMEOUT: Duration = Duration::from_secs(300);
 
 #[typetag::serde(tag = "type")]
 #[async_trait]
 pub trait Job: Send + Sync + RefUnwindSafe {
-    async fn handle(&self) -> Result<(), Error>;
+    /// Process the job, `ctx` is cancelled when the job should stop early.
+    async fn handle(&self, ctx: &JobContext) -> Result<(), Error>;
 
     /// The name of the queue the job should be dispatched to.
     fn queue(&self) -> String {
@@ -17,18 +22,58 @@ pub trait Job: Send + Sync + RefUnwindSafe {
     }
 
     /// The number of times the job may be attempted.
-    fn tries(&self) -> i16 {
+    fn tries(&self) -> u32 {
         TRIES
     }
 
-    /// The number of seconds the job can run before timing out.
-    fn timeout(&self) -> i16 {
+    /// Keep retrying the job until this deadline instead of using `tries`.
+    ///
+    /// Evaluated once when the job is dispatched.
+    fn retry_until(&self) -> Option<SystemTime> {
+        None
+    }
+
+    /// Discard the job if it hasn't started within this duration after being dispatched.
+    fn ttl(&self) -> Option<Duration> {
+        None
+    }
+
+    /// How long the job can run before timing out.
+    fn timeout(&self) -> Duration {
         TIMEOUT
     }
 
-    /// Calculate the number of seconds to wait before retrying the job.
-    fn backoff(&self, attempt: u32) -> u32 {
-        u32::pow(2, attempt)
+    /// The backoff used to delay retries, the worker's default is used when `None`.
+    fn backoff(&self) -> Option<Backoff> {
+        None
+    }
+
+    /// How many jobs of this type may run at once across all workers, jobs over
+    /// the limit are released back to the queue without using an attempt. A job
+    /// with a limit of 0 can't be dispatched, or fails if it was.
+    fn max_concurrency(&self) -> Option<u32> {
+        None
+    }
+
+    /// Jobs sharing this key never run at the same time across all workers, a job
+    /// finding the key taken is released back to the queue without using an attempt.
+    ///
+    /// The lock is kept alive by the worker's heartbeat, so it expires after the
+    /// worker's `heartbeat_timeout` if the worker crashes.
+    fn overlap_key(&self) -> Option<String> {
+        None
+    }
+
+    /// Limit how often jobs sharing the key of the limit may start, jobs over
+    /// the limit are released back to the queue without using an attempt. A job
+    /// with a limit of 0 can't be dispatched, or fails if it was.
+    fn rate_limit(&self) -> Option<RateLimit> {
+        None
+    }
+
+    /// Middleware wrapping the handling of this job, run after the worker's.
+    fn middleware(&self) -> Vec<Arc<dyn JobMiddleware>> {
+        Vec::new()
     }
 
     /// Handle a job failure.
@@ -36,3 +81,21 @@ pub trait Job: Send + Sync + RefUnwindSafe {
         Ok(())
     }
 }
+
+/// Reject limits no job could ever start under, the job would otherwise be
+/// released back to the queue forever.
+pub(crate) fn validate_limits(job: &dyn Job) -> Result<(), Error> {
+    if job.max_concurrency() == Some(0) {
+        return Err(Error::InvalidConfiguration(
+            "max_concurrency must be at least 1".to_string(),
+        ));
+    }
+
+    if job.rate_limit().is_some_and(|limit| limit.max() == 0) {
+        return Err(Error::InvalidConfiguration(
+            "a rate limit must allow at least one job".to_string(),
+        ));
+    }
+
+    Ok(())
+}
@@ -1,10 +1,20 @@
 #![doc = include_str!("../docs/getting-started.md")]
 
+mod backlog;
+mod backoff;
 mod client;
+mod context;
 mod errors;
 mod job;
+mod locks;
+mod memory;
+mod middleware;
 pub(crate) mod models;
 mod pool;
+mod rate_limit;
+mod registry;
+mod slots;
+mod supervisor;
 mod worker;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -13,11 +23,18 @@ pub enum DBType {
     Mysql,
 }
 
+pub use backoff::{Backoff, Jitter};
 pub use client::{Client, ClientBuilder, DispatchOptions};
+pub use context::JobContext;
 pub use errors::Error;
 pub use job::Job;
+pub use middleware::{JobMiddleware, Next};
 pub(crate) use pool::{get_pool, PoolOptions};
-pub use worker::{Worker, WorkerBuilder};
+pub use rate_limit::RateLimit;
+pub use registry::{ReservedJob, WorkerInfo};
+pub use supervisor::{Supervisor, SupervisorBuilder};
+pub use tokio_util::sync::CancellationToken;
+pub use worker::{JobOutcome, Worker, WorkerBuilder};
 
 #[doc(hidden)]
 pub extern crate serde;
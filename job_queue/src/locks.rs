@@ -0,0 +1,118 @@
+use crate::{models::unix_timestamp, DBType, Error};
+use sqlx::AnyPool;
+use std::time::{Duration, SystemTime};
+
+/// A row of the `job_locks` table, held by a running job until it finishes or
+/// until the lock expires because the worker stopped refreshing it.
+pub(crate) struct Lock<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) job_id: i64,
+    pub(crate) owner: &'a str,
+    pub(crate) ttl: Duration,
+}
+
+/// Take the lock unless another job holds it and it hasn't expired yet. A lock
+/// the same job still holds, left behind by an earlier attempt, is taken again.
+pub(crate) async fn acquire(
+    pool: &AnyPool,
+    db_type: DBType,
+    lock: &Lock<'_>,
+) -> Result<bool, Error> {
+    let time = unix_timestamp(SystemTime::now())?;
+
+    sqlx::query(&format!(
+        "DELETE FROM job_locks WHERE name = {} AND (expires_at < {} OR job_id = {})",
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$1",
+        },
+        time,
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$2",
+        }
+    ))
+    .bind(lock.name)
+    .bind(lock.job_id)
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    let result = sqlx::query(match db_type {
+        DBType::Mysql => {
+            "INSERT IGNORE INTO job_locks (name, job_id, owner, expires_at) VALUES (?, ?, ?, ?)"
+        }
+        DBType::Postgres => {
+            r"INSERT INTO job_locks (name, job_id, owner, expires_at) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (name) DO NOTHING"
+        }
+    })
+    .bind(lock.name)
+    .bind(lock.job_id)
+    .bind(lock.owner)
+    .bind(time + lock.ttl.as_secs() as i64)
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub(crate) async fn release(
+    pool: &AnyPool,
+    db_type: DBType,
+    name: &str,
+    job_id: i64,
+) -> Result<(), Error> {
+    sqlx::query(&format!(
+        "DELETE FROM job_locks WHERE name = {} AND job_id = {}",
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$1",
+        },
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$2",
+        }
+    ))
+    .bind(name)
+    .bind(job_id)
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(())
+}
+
+/// Extend the locks held by the given running jobs of a worker.
+pub(crate) async fn refresh(
+    pool: &AnyPool,
+    db_type: DBType,
+    owner: &str,
+    job_ids: &[i64],
+    ttl: Duration,
+) -> Result<(), Error> {
+    if job_ids.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(&format!(
+        "UPDATE job_locks SET expires_at = {} WHERE owner = {} AND job_id IN ({})",
+        unix_timestamp(SystemTime::now())? + ttl.as_secs() as i64,
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$1",
+        },
+        job_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+    .bind(owner)
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(())
+}
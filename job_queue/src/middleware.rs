@@ -0,0 +1,55 @@
+use crate::{Error, Job, JobContext};
+use async_trait::async_trait;
+use std::{panic::RefUnwindSafe, sync::Arc};
+
+/// Wraps the handling of jobs, registered for every job with
+/// [`WorkerBuilder::middleware`](crate::WorkerBuilder::middleware) or for a job
+/// type with [`Job::middleware`].
+///
+/// ```
+/// use job_queue::{async_trait, Error, Job, JobContext, JobMiddleware, Next};
+/// use std::time::Instant;
+///
+/// struct Timing;
+///
+/// #[async_trait::async_trait]
+/// impl JobMiddleware for Timing {
+///     async fn handle(&self, job: &dyn Job, ctx: &JobContext, next: Next<'_>) -> Result<(), Error> {
+///         let start = Instant::now();
+///         let result = next.run(ctx).await;
+///
+///         println!("{} took {:?}", job.typetag_name(), start.elapsed());
+///
+///         result
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait JobMiddleware: Send + Sync + RefUnwindSafe {
+    /// Call `next.run(ctx)` to continue with the next middleware, and eventually
+    /// the job itself, or return early to skip it.
+    async fn handle(&self, job: &dyn Job, ctx: &JobContext, next: Next<'_>) -> Result<(), Error>;
+}
+
+/// The rest of the middleware chain, ending with [`Job::handle`].
+pub struct Next<'a> {
+    job: &'a dyn Job,
+    middleware: &'a [Arc<dyn JobMiddleware>],
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(job: &'a dyn Job, middleware: &'a [Arc<dyn JobMiddleware>]) -> Self {
+        Self { job, middleware }
+    }
+
+    pub async fn run(self, ctx: &JobContext) -> Result<(), Error> {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => {
+                middleware
+                    .handle(self.job, ctx, Next::new(self.job, rest))
+                    .await
+            }
+            None => self.job.handle(ctx).await,
+        }
+    }
+}
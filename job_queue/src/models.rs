@@ -1,8 +1,11 @@
+use crate::{Backoff, Error};
+use serde::{Deserialize, Serialize};
 use sqlx::any::AnyTypeInfo;
 use sqlx::decode::Decode;
 use sqlx::postgres::any::{AnyTypeInfoKind, AnyValueKind};
 use sqlx::{database::HasValueRef, error::BoxDynError, Any};
 use sqlx::{Type, ValueRef};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
 pub struct JsonValue(pub serde_json::Value);
@@ -12,21 +15,66 @@ pub struct Task {
     pub id: i64,
     pub uuid: String,
     pub payload: JsonValue,
-    pub attempts: i16,
+    pub attempts: i64,
+    pub options: Option<JsonValue>,
+    pub cancelled_at: Option<i64>,
+    /// The backoff delay waited before this attempt, in milliseconds.
+    pub backoff_ms: Option<i64>,
     // available_at: i64,
     // created_at: i64,
 }
 
+impl Task {
+    pub fn options(&self) -> Result<JobOptions, Error> {
+        match &self.options {
+            Some(options) => JobOptions::deserialize(&options.0).map_err(Error::SerdeError),
+            None => Ok(JobOptions::default()),
+        }
+    }
+}
+
 #[derive(Debug, sqlx::FromRow)]
 pub struct FailedJob {
-    pub id: i64,
-    pub uuid: String,
     pub queue: String,
     pub payload: JsonValue,
+    pub options: Option<JsonValue>,
     // available_at: i64,
     // created_at: i64,
 }
 
+/// Settings resolved when the job is dispatched and stored next to its payload,
+/// so they survive the job being re-serialized on retry.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct JobOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_until: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tries: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<Backoff>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_parent: Option<String>,
+}
+
+impl JobOptions {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_ms.map(Duration::from_millis)
+    }
+}
+
+pub fn unix_timestamp(time: SystemTime) -> Result<i64, Error> {
+    Ok(time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| Error::Unknown)?
+        .as_secs() as i64)
+}
+
 impl Type<Any> for JsonValue {
     fn type_info() -> AnyTypeInfo {
         AnyTypeInfo {
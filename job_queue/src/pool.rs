@@ -5,10 +5,28 @@ pub(crate) struct PoolOptions {
     pub(crate) min_connections: u32,
 }
 
+fn validate(options: &PoolOptions) -> Result<(), Error> {
+    if options.max_connections == 0 {
+        return Err(Error::InvalidConfiguration(
+            "max_connections must be at least 1".to_string(),
+        ));
+    }
+
+    if options.min_connections > options.max_connections {
+        return Err(Error::InvalidConfiguration(
+            "min_connections can't be greater than max_connections".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
 pub(crate) async fn get_pool(
     database_url: &str,
     options: PoolOptions,
 ) -> Result<(sqlx::Pool<sqlx::Any>, DBType), Error> {
+    validate(&options)?;
+
     sqlx::any::install_default_drivers();
 
     let pool = sqlx::any::AnyPoolOptions::new()
@@ -37,8 +55,15 @@ pub(crate) async fn get_pool(
                 `reserved_at` int unsigned DEFAULT NULL,
                 `available_at` int unsigned NOT NULL,
                 `created_at` int unsigned NOT NULL,
+                `options` text COLLATE utf8mb4_unicode_ci,
+                `cancelled_at` bigint DEFAULT NULL,
+                `heartbeat_at` bigint DEFAULT NULL,
+                `reserved_by` varchar(255) COLLATE utf8mb4_unicode_ci DEFAULT NULL,
+                `group_key` varchar(255) COLLATE utf8mb4_unicode_ci DEFAULT NULL,
+                `backoff_ms` bigint DEFAULT NULL,
                 PRIMARY KEY (`id`),
-                KEY `jobs_queue_index` (`queue`)
+                KEY `jobs_queue_index` (`queue`),
+                KEY `jobs_group_key_index` (`group_key`, `id`)
               ) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci",
         )
         .execute(&pool)
@@ -52,6 +77,7 @@ pub(crate) async fn get_pool(
             `queue` text COLLATE utf8mb4_unicode_ci NOT NULL,
             `payload` longtext COLLATE utf8mb4_unicode_ci NOT NULL,
             `exception` longtext COLLATE utf8mb4_unicode_ci NOT NULL,
+            `options` text COLLATE utf8mb4_unicode_ci,
             `failed_at` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
             PRIMARY KEY (`id`),
             UNIQUE KEY `failed_jobs_uuid_unique` (`uuid`)
@@ -60,6 +86,87 @@ pub(crate) async fn get_pool(
         .execute(&pool)
         .await
         .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r" CREATE TABLE IF NOT EXISTS `cancelled_jobs` (
+            `id` bigint unsigned NOT NULL AUTO_INCREMENT,
+            `uuid` varchar(255) COLLATE utf8mb4_unicode_ci NOT NULL,
+            `queue` text COLLATE utf8mb4_unicode_ci NOT NULL,
+            `payload` longtext COLLATE utf8mb4_unicode_ci NOT NULL,
+            `options` text COLLATE utf8mb4_unicode_ci,
+            `cancelled_at` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (`id`),
+            UNIQUE KEY `cancelled_jobs_uuid_unique` (`uuid`)
+          ) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci",
+        )
+        .execute(&pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r" CREATE TABLE IF NOT EXISTS `workers` (
+            `id` varchar(255) COLLATE utf8mb4_unicode_ci NOT NULL,
+            `hostname` varchar(255) COLLATE utf8mb4_unicode_ci NOT NULL,
+            `pid` bigint NOT NULL,
+            `queues` text COLLATE utf8mb4_unicode_ci NOT NULL,
+            `concurrency` bigint NOT NULL,
+            `started_at` bigint NOT NULL,
+            `last_seen` bigint NOT NULL,
+            `expires_at` bigint NOT NULL,
+            PRIMARY KEY (`id`)
+          ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci",
+        )
+        .execute(&pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r" CREATE TABLE IF NOT EXISTS `paused_queues` (
+            `queue` varchar(255) COLLATE utf8mb4_unicode_ci NOT NULL,
+            `paused_at` bigint NOT NULL,
+            PRIMARY KEY (`queue`)
+          ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci",
+        )
+        .execute(&pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r" CREATE TABLE IF NOT EXISTS `worker_signals` (
+            `name` varchar(255) COLLATE utf8mb4_unicode_ci NOT NULL,
+            `issued_at` bigint NOT NULL,
+            PRIMARY KEY (`name`)
+          ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci",
+        )
+        .execute(&pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r" CREATE TABLE IF NOT EXISTS `job_locks` (
+            `name` varchar(255) COLLATE utf8mb4_unicode_ci NOT NULL,
+            `job_id` bigint NOT NULL,
+            `owner` varchar(255) COLLATE utf8mb4_unicode_ci NOT NULL,
+            `expires_at` bigint NOT NULL,
+            PRIMARY KEY (`name`)
+          ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci",
+        )
+        .execute(&pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r" CREATE TABLE IF NOT EXISTS `rate_limits` (
+            `name` varchar(255) COLLATE utf8mb4_unicode_ci NOT NULL,
+            `window_start` bigint NOT NULL,
+            `hits` bigint NOT NULL,
+            `previous_hits` bigint NOT NULL,
+            PRIMARY KEY (`name`)
+          ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci",
+        )
+        .execute(&pool)
+        .await
+        .map_err(Error::DatabaseError)?;
     } else if database_url.starts_with("postgres") {
         let mut transaction = pool.begin().await?;
 
@@ -74,10 +181,16 @@ pub(crate) async fn get_pool(
                     uuid text NOT NULL,
                     queue text NOT NULL,
                     payload text NOT NULL,
-                    attempts int2 NOT NULL,
-                    reserved_at int4,
-                    available_at int4 NOT NULL,
-                    created_at int4 NOT NULL,
+                    attempts int8 NOT NULL,
+                    reserved_at int8,
+                    available_at int8 NOT NULL,
+                    created_at int8 NOT NULL,
+                    options text,
+                    cancelled_at int8,
+                    heartbeat_at int8,
+                    reserved_by text,
+                    group_key text,
+                    backoff_ms int8,
                     PRIMARY KEY (id)
                 )
                 "#,
@@ -99,6 +212,7 @@ pub(crate) async fn get_pool(
                     "queue" text NOT NULL,
                     "payload" text NOT NULL,
                     "exception" text NOT NULL,
+                    "options" text,
                     "failed_at" timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
                     PRIMARY KEY ("id")
                 )
@@ -108,10 +222,266 @@ pub(crate) async fn get_pool(
         .await
         .map_err(Error::DatabaseError)?;
 
+        sqlx::query(" CREATE SEQUENCE IF NOT EXISTS cancelled_jobs_id_seq")
+            .execute(&mut *transaction)
+            .await
+            .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r#"
+                CREATE TABLE IF NOT EXISTS public.cancelled_jobs (
+                    "id" int8 NOT NULL DEFAULT nextval('cancelled_jobs_id_seq'::regclass),
+                    "uuid" text NOT NULL,
+                    "queue" text NOT NULL,
+                    "payload" text NOT NULL,
+                    "options" text,
+                    "cancelled_at" timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY ("id")
+                )
+                "#,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r#"
+                CREATE TABLE IF NOT EXISTS public.workers (
+                    "id" text NOT NULL,
+                    "hostname" text NOT NULL,
+                    "pid" int8 NOT NULL,
+                    "queues" text NOT NULL,
+                    "concurrency" int8 NOT NULL,
+                    "started_at" int8 NOT NULL,
+                    "last_seen" int8 NOT NULL,
+                    "expires_at" int8 NOT NULL,
+                    PRIMARY KEY ("id")
+                )
+                "#,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r#"
+                CREATE TABLE IF NOT EXISTS public.paused_queues (
+                    "queue" text NOT NULL,
+                    "paused_at" int8 NOT NULL,
+                    PRIMARY KEY ("queue")
+                )
+                "#,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r#"
+                CREATE TABLE IF NOT EXISTS public.worker_signals (
+                    "name" text NOT NULL,
+                    "issued_at" int8 NOT NULL,
+                    PRIMARY KEY ("name")
+                )
+                "#,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r#"
+                CREATE TABLE IF NOT EXISTS public.job_locks (
+                    "name" text NOT NULL,
+                    "job_id" int8 NOT NULL,
+                    "owner" text NOT NULL,
+                    "expires_at" int8 NOT NULL,
+                    PRIMARY KEY ("name")
+                )
+                "#,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        sqlx::query(
+            r#"
+                CREATE TABLE IF NOT EXISTS public.rate_limits (
+                    "name" text NOT NULL,
+                    "window_start" int8 NOT NULL,
+                    "hits" int8 NOT NULL,
+                    "previous_hits" int8 NOT NULL,
+                    PRIMARY KEY ("name")
+                )
+                "#,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(Error::DatabaseError)?;
+
         transaction.commit().await.map_err(Error::DatabaseError)?;
+
+        widen_columns(
+            &pool,
+            "jobs",
+            &["attempts", "reserved_at", "available_at", "created_at"],
+        )
+        .await?;
     } else {
         return Err(Error::UnsupportedDatabaseUrl);
     }
 
+    add_column(&pool, db_type, "jobs", "options", "text").await?;
+    add_column(&pool, db_type, "failed_jobs", "options", "text").await?;
+    add_column(&pool, db_type, "jobs", "cancelled_at", "bigint").await?;
+    add_column(&pool, db_type, "jobs", "heartbeat_at", "bigint").await?;
+    add_column(&pool, db_type, "jobs", "reserved_by", "varchar(255)").await?;
+    add_column(&pool, db_type, "jobs", "group_key", "varchar(255)").await?;
+    add_column(&pool, db_type, "jobs", "backoff_ms", "bigint").await?;
+    add_index(
+        &pool,
+        db_type,
+        "jobs",
+        "jobs_group_key_index",
+        &["group_key", "id"],
+    )
+    .await?;
+
     Ok((pool, db_type))
 }
+
+/// Add a column to a table created by an older version of the crate.
+async fn add_column(
+    pool: &sqlx::Pool<sqlx::Any>,
+    db_type: DBType,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), Error> {
+    match db_type {
+        DBType::Mysql => {
+            let (count,) = sqlx::query_as::<sqlx::Any, (i64,)>(
+                r"SELECT COUNT(*) FROM information_schema.columns
+                WHERE table_schema = DATABASE() AND table_name = ? AND column_name = ?",
+            )
+            .bind(table)
+            .bind(column)
+            .fetch_one(pool)
+            .await
+            .map_err(Error::DatabaseError)?;
+
+            if count == 0 {
+                sqlx::query(&format!(
+                    "ALTER TABLE `{}` ADD COLUMN `{}` {}",
+                    table, column, definition
+                ))
+                .execute(pool)
+                .await
+                .map_err(Error::DatabaseError)?;
+            }
+        }
+        DBType::Postgres => {
+            sqlx::query(&format!(
+                r#"ALTER TABLE public.{} ADD COLUMN IF NOT EXISTS "{}" {}"#,
+                table, column, definition
+            ))
+            .execute(pool)
+            .await
+            .map_err(Error::DatabaseError)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add an index to a table unless it exists already, such as on a table created
+/// by an older version of the crate.
+async fn add_index(
+    pool: &sqlx::Pool<sqlx::Any>,
+    db_type: DBType,
+    table: &str,
+    index: &str,
+    columns: &[&str],
+) -> Result<(), Error> {
+    match db_type {
+        DBType::Mysql => {
+            let (count,) = sqlx::query_as::<sqlx::Any, (i64,)>(
+                r"SELECT COUNT(*) FROM information_schema.statistics
+                WHERE table_schema = DATABASE() AND table_name = ? AND index_name = ?",
+            )
+            .bind(table)
+            .bind(index)
+            .fetch_one(pool)
+            .await
+            .map_err(Error::DatabaseError)?;
+
+            if count == 0 {
+                sqlx::query(&format!(
+                    "CREATE INDEX `{}` ON `{}` ({})",
+                    index,
+                    table,
+                    columns
+                        .iter()
+                        .map(|column| format!("`{}`", column))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+                .execute(pool)
+                .await
+                .map_err(Error::DatabaseError)?;
+            }
+        }
+        DBType::Postgres => {
+            sqlx::query(&format!(
+                r#"CREATE INDEX IF NOT EXISTS "{}" ON public.{} ({})"#,
+                index,
+                table,
+                columns
+                    .iter()
+                    .map(|column| format!(r#""{}""#, column))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .execute(pool)
+            .await
+            .map_err(Error::DatabaseError)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert Postgres integer columns created by an older version of the crate to `int8`.
+async fn widen_columns(
+    pool: &sqlx::Pool<sqlx::Any>,
+    table: &str,
+    columns: &[&str],
+) -> Result<(), Error> {
+    let narrow_columns = sqlx::query_as::<sqlx::Any, (String,)>(
+        r"SELECT column_name::text FROM information_schema.columns
+        WHERE table_schema = 'public' AND table_name = $1 AND data_type <> 'bigint'",
+    )
+    .bind(table)
+    .fetch_all(pool)
+    .await
+    .map_err(Error::DatabaseError)?
+    .into_iter()
+    .filter(|(column,)| columns.contains(&column.as_str()))
+    .map(|(column,)| format!(r#"ALTER COLUMN "{}" TYPE int8"#, column))
+    .collect::<Vec<_>>();
+
+    if narrow_columns.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(&format!(
+        "ALTER TABLE public.{} {}",
+        table,
+        narrow_columns.join(", ")
+    ))
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use crate::{models::unix_timestamp, DBType, Error};
+use sqlx::{Any, AnyPool};
+use std::time::{Duration, SystemTime};
+
+/// Allow at most `max` jobs sharing the same key to start per `period`, across
+/// all workers.
+///
+/// Starts are counted in a sliding window: the count of the previous period is
+/// weighted by how much of it still overlaps the window ending now.
+///
+/// ```
+/// use job_queue::RateLimit;
+///
+/// let limit = RateLimit::per_minute("stripe-api", 100);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    key: String,
+    max: u32,
+    period: Duration,
+}
+
+impl RateLimit {
+    /// `period` is rounded up to whole seconds.
+    pub fn new(key: impl Into<String>, max: u32, period: Duration) -> Self {
+        Self {
+            key: key.into(),
+            max,
+            period,
+        }
+    }
+
+    pub fn per_second(key: impl Into<String>, max: u32) -> Self {
+        Self::new(key, max, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(key: impl Into<String>, max: u32) -> Self {
+        Self::new(key, max, Duration::from_secs(60))
+    }
+
+    pub fn per_hour(key: impl Into<String>, max: u32) -> Self {
+        Self::new(key, max, Duration::from_secs(3600))
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn max(&self) -> u32 {
+        self.max
+    }
+
+    fn period_secs(&self) -> i64 {
+        (self.period.as_secs() + u64::from(self.period.subsec_nanos() > 0)).max(1) as i64
+    }
+}
+
+/// Count a start against the limit. Returns `None` when the job may start, or
+/// the number of seconds to wait before trying again.
+pub(crate) async fn hit(
+    pool: &AnyPool,
+    db_type: DBType,
+    limit: &RateLimit,
+) -> Result<Option<i64>, Error> {
+    let time = unix_timestamp(SystemTime::now())?;
+    let period = limit.period_secs();
+
+    sqlx::query(match db_type {
+        DBType::Mysql => {
+            "INSERT IGNORE INTO rate_limits (name, window_start, hits, previous_hits) VALUES (?, ?, 0, 0)"
+        }
+        DBType::Postgres => {
+            r"INSERT INTO rate_limits (name, window_start, hits, previous_hits) VALUES ($1, $2, 0, 0)
+            ON CONFLICT (name) DO NOTHING"
+        }
+    })
+    .bind(&limit.key)
+    .bind(window_start(time, period))
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    let mut transaction = pool.begin().await.map_err(Error::DatabaseError)?;
+
+    let stored = sqlx::query_as::<Any, (i64, i64, i64)>(&format!(
+        "SELECT window_start, hits, previous_hits FROM rate_limits WHERE name = {} FOR UPDATE",
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$1",
+        }
+    ))
+    .bind(&limit.key)
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    let window = Window::at(time, period, stored);
+
+    if let Some(delay) = window.delay(time, period, i64::from(limit.max)) {
+        transaction.rollback().await.map_err(Error::DatabaseError)?;
+
+        return Ok(Some(delay));
+    }
+
+    sqlx::query(&format!(
+        "UPDATE rate_limits SET window_start = {}, hits = {}, previous_hits = {} WHERE name = {}",
+        window.start,
+        window.current + 1,
+        window.previous,
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$1",
+        }
+    ))
+    .bind(&limit.key)
+    .execute(&mut *transaction)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    transaction.commit().await.map_err(Error::DatabaseError)?;
+
+    Ok(None)
+}
+
+fn window_start(time: i64, period: i64) -> i64 {
+    time - time.rem_euclid(period)
+}
+
+/// The starts counted in the period containing a given time and in the one
+/// before it.
+#[derive(Debug, PartialEq, Eq)]
+struct Window {
+    start: i64,
+    previous: i64,
+    current: i64,
+}
+
+impl Window {
+    /// Shift the `(window_start, hits, previous_hits)` stored for a limit to the
+    /// period containing `time`.
+    fn at(time: i64, period: i64, (stored_start, hits, previous_hits): (i64, i64, i64)) -> Self {
+        let start = window_start(time, period);
+
+        let (previous, current) = if stored_start == start {
+            (previous_hits, hits)
+        } else if stored_start == start - period {
+            (hits, 0)
+        } else {
+            (0, 0)
+        };
+
+        Self {
+            start,
+            previous,
+            current,
+        }
+    }
+
+    /// The number of seconds to wait before one more start fits under `max`, or
+    /// `None` if it fits now.
+    fn delay(&self, time: i64, period: i64, max: i64) -> Option<i64> {
+        let elapsed = time - self.start;
+        let weight = (period - elapsed) as f64 / period as f64;
+
+        if self.previous as f64 * weight + self.current as f64 + 1.0 <= max as f64 {
+            return None;
+        }
+
+        let delay = if self.current + 1 > max || self.previous == 0 {
+            period - elapsed
+        } else {
+            // The previous period's weight has to shrink until one more start fits.
+            let overlap = (max - self.current - 1) as f64 * period as f64 / self.previous as f64;
+
+            (period as f64 - overlap).ceil() as i64 - elapsed
+        };
+
+        Some(delay.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Window;
+
+    #[test]
+    fn window_shifts_to_the_current_period() {
+        let window = |stored| Window::at(150, 60, stored);
+
+        assert_eq!(
+            window((120, 4, 7)),
+            Window {
+                start: 120,
+                previous: 7,
+                current: 4
+            }
+        );
+        assert_eq!(
+            window((60, 4, 7)),
+            Window {
+                start: 120,
+                previous: 4,
+                current: 0
+            }
+        );
+        assert_eq!(
+            window((0, 4, 7)),
+            Window {
+                start: 120,
+                previous: 0,
+                current: 0
+            }
+        );
+    }
+
+    #[test]
+    fn delay_weights_the_previous_period() {
+        let window = |previous, current| Window {
+            start: 120,
+            previous,
+            current,
+        };
+
+        assert_eq!(window(10, 0).delay(150, 60, 10), None);
+        assert_eq!(window(10, 4).delay(150, 60, 10), None);
+        assert_eq!(window(10, 5).delay(150, 60, 10), Some(6));
+        assert_eq!(window(10, 5).delay(156, 60, 10), None);
+    }
+
+    #[test]
+    fn delay_waits_for_the_next_period_when_full() {
+        let window = Window {
+            start: 120,
+            previous: 0,
+            current: 10,
+        };
+
+        assert_eq!(window.delay(150, 60, 10), Some(30));
+        assert_eq!(window.delay(179, 60, 10), Some(1));
+    }
+}
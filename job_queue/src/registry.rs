@@ -0,0 +1,257 @@
+use crate::{
+    models::{unix_timestamp, JsonValue},
+    DBType, Error,
+};
+use sqlx::{Any, AnyPool};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A worker registered in the `workers` table.
+#[derive(Debug, Clone)]
+pub struct WorkerInfo {
+    pub id: String,
+    pub hostname: String,
+    pub pid: u32,
+    pub queues: Vec<String>,
+    pub concurrency: u32,
+    pub started_at: SystemTime,
+    pub last_seen: SystemTime,
+    /// The jobs currently reserved by the worker.
+    pub jobs: Vec<ReservedJob>,
+}
+
+/// A job reserved by a worker.
+#[derive(Debug, Clone)]
+pub struct ReservedJob {
+    pub id: i64,
+    pub uuid: String,
+    pub job_type: String,
+    pub queue: String,
+    pub attempts: u32,
+    pub reserved_at: SystemTime,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct WorkerRow {
+    id: String,
+    hostname: String,
+    pid: i64,
+    queues: JsonValue,
+    concurrency: i64,
+    started_at: i64,
+    last_seen: i64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ReservedJobRow {
+    id: i64,
+    uuid: String,
+    queue: String,
+    payload: JsonValue,
+    attempts: i64,
+    reserved_at: i64,
+    reserved_by: String,
+}
+
+pub(crate) struct Registration<'a> {
+    pub(crate) id: &'a str,
+    pub(crate) queues: &'a [String],
+    pub(crate) concurrency: u32,
+    pub(crate) started_at: i64,
+    pub(crate) timeout: Duration,
+}
+
+pub(crate) async fn register(
+    pool: &AnyPool,
+    db_type: DBType,
+    registration: &Registration<'_>,
+) -> Result<(), Error> {
+    let time = unix_timestamp(SystemTime::now())?;
+
+    sqlx::query(&format!(
+        r#"
+        INSERT INTO workers (id, hostname, pid, queues, concurrency, started_at, last_seen, expires_at)
+        VALUES {}
+        "#,
+        match db_type {
+            DBType::Mysql => "(?, ?, ?, ?, ?, ?, ?, ?)",
+            DBType::Postgres => "($1, $2, $3, $4, $5, $6, $7, $8)",
+        }
+    ))
+    .bind(registration.id)
+    .bind(hostname())
+    .bind(std::process::id() as i64)
+    .bind(serde_json::to_string(registration.queues).map_err(Error::SerdeError)?)
+    .bind(registration.concurrency as i64)
+    .bind(registration.started_at)
+    .bind(time)
+    .bind(time + registration.timeout.as_secs() as i64)
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(())
+}
+
+/// Refresh the worker's `last_seen`, registering it again if it was reaped,
+/// and remove the workers that stopped refreshing theirs.
+pub(crate) async fn refresh(
+    pool: &AnyPool,
+    db_type: DBType,
+    registration: &Registration<'_>,
+) -> Result<(), Error> {
+    let time = unix_timestamp(SystemTime::now())?;
+
+    let result = sqlx::query(&format!(
+        "UPDATE workers SET last_seen = {}, expires_at = {}, concurrency = {} WHERE id = {}",
+        time,
+        time + registration.timeout.as_secs() as i64,
+        registration.concurrency,
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$1",
+        }
+    ))
+    .bind(registration.id)
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        register(pool, db_type, registration).await?;
+    }
+
+    sqlx::query(&format!("DELETE FROM workers WHERE expires_at < {}", time))
+        .execute(pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+    Ok(())
+}
+
+pub(crate) async fn unregister(pool: &AnyPool, db_type: DBType, id: &str) -> Result<(), Error> {
+    sqlx::query(&format!(
+        "DELETE FROM workers WHERE id = {}",
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$1",
+        }
+    ))
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(())
+}
+
+pub(crate) async fn list(pool: &AnyPool) -> Result<Vec<WorkerInfo>, Error> {
+    let time = unix_timestamp(SystemTime::now())?;
+
+    let workers = sqlx::query_as::<Any, WorkerRow>(&format!(
+        r#"
+        SELECT id, hostname, pid, queues, concurrency, started_at, last_seen
+        FROM workers
+        WHERE expires_at >= {}
+        ORDER BY started_at ASC
+        "#,
+        time
+    ))
+    .fetch_all(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    let jobs = sqlx::query_as::<Any, ReservedJobRow>(
+        r#"
+        SELECT id, uuid, queue, payload, attempts, reserved_at, reserved_by
+        FROM jobs
+        WHERE reserved_by IS NOT NULL AND reserved_at IS NOT NULL
+        ORDER BY id ASC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(workers
+        .into_iter()
+        .map(|worker| WorkerInfo {
+            jobs: jobs
+                .iter()
+                .filter(|job| job.reserved_by == worker.id)
+                .map(|job| ReservedJob {
+                    id: job.id,
+                    uuid: job.uuid.clone(),
+                    job_type: job.payload.0["type"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    queue: job.queue.clone(),
+                    attempts: job.attempts as u32,
+                    reserved_at: from_unix_timestamp(job.reserved_at),
+                })
+                .collect(),
+            id: worker.id,
+            hostname: worker.hostname,
+            pid: worker.pid as u32,
+            queues: serde_json::from_value(worker.queues.0).unwrap_or_default(),
+            concurrency: worker.concurrency as u32,
+            started_at: from_unix_timestamp(worker.started_at),
+            last_seen: from_unix_timestamp(worker.last_seen),
+        })
+        .collect())
+}
+
+const RESTART_SIGNAL: &str = "restart";
+
+pub(crate) async fn signal_restart(pool: &AnyPool, db_type: DBType) -> Result<(), Error> {
+    let time = unix_timestamp(SystemTime::now())?;
+
+    sqlx::query(match db_type {
+        DBType::Mysql => {
+            r"INSERT INTO worker_signals (name, issued_at) VALUES (?, ?)
+            ON DUPLICATE KEY UPDATE issued_at = VALUES(issued_at)"
+        }
+        DBType::Postgres => {
+            r"INSERT INTO worker_signals (name, issued_at) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET issued_at = EXCLUDED.issued_at"
+        }
+    })
+    .bind(RESTART_SIGNAL)
+    .bind(time)
+    .execute(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(())
+}
+
+/// When the last restart was requested through [`signal_restart`].
+pub(crate) async fn last_restart(pool: &AnyPool, db_type: DBType) -> Result<Option<i64>, Error> {
+    let row = sqlx::query_as::<Any, (i64,)>(&format!(
+        "SELECT issued_at FROM worker_signals WHERE name = {}",
+        match db_type {
+            DBType::Mysql => "?",
+            DBType::Postgres => "$1",
+        }
+    ))
+    .bind(RESTART_SIGNAL)
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::DatabaseError)?;
+
+    Ok(row.map(|(issued_at,)| issued_at))
+}
+
+fn from_unix_timestamp(timestamp: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(timestamp.max(0) as u64)
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
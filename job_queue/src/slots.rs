@@ -0,0 +1,156 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// The permits a worker runs its jobs under. The size can change while jobs hold
+/// permits: growing adds permits at once, shrinking takes away the free ones at
+/// once and the others as the jobs holding them finish.
+pub(crate) struct Slots {
+    size: Mutex<u32>,
+    semaphore: Arc<Semaphore>,
+    /// Permits still to be taken away after the size was lowered.
+    excess: AtomicU32,
+}
+
+impl Slots {
+    pub(crate) fn new(size: u32) -> Self {
+        Self {
+            size: Mutex::new(size),
+            semaphore: Arc::new(Semaphore::new(size as usize)),
+            excess: AtomicU32::new(0),
+        }
+    }
+
+    pub(crate) fn size(&self) -> u32 {
+        *self.size.lock().unwrap()
+    }
+
+    /// Change the number of permits, returns the previous one.
+    pub(crate) fn resize(&self, size: u32) -> u32 {
+        let mut current = self.size.lock().unwrap();
+        let previous = *current;
+
+        if size > previous {
+            let added = size - previous;
+            let pending = self
+                .excess
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |excess| {
+                    Some(excess - excess.min(added))
+                })
+                .unwrap_or_default();
+
+            self.semaphore
+                .add_permits((added - pending.min(added)) as usize);
+        } else if size < previous {
+            self.excess.fetch_add(previous - size, Ordering::SeqCst);
+
+            while let Ok(permit) = self.semaphore.try_acquire() {
+                if !self.take_excess() {
+                    break;
+                }
+
+                permit.forget();
+            }
+        }
+
+        *current = size;
+
+        previous
+    }
+
+    /// Wait for a free permit, `None` if the semaphore was closed.
+    pub(crate) async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        loop {
+            let permit = self.semaphore.clone().acquire_owned().await.ok()?;
+
+            if !self.take_excess() {
+                return Some(permit);
+            }
+
+            permit.forget();
+        }
+    }
+
+    /// Give a permit back, unless it has to be taken away after a shrink.
+    pub(crate) fn release(&self, permit: OwnedSemaphorePermit) {
+        if self.take_excess() {
+            permit.forget();
+        }
+    }
+
+    /// Claim one of the permits still to be taken away, if any.
+    fn take_excess(&self) -> bool {
+        self.excess
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |excess| {
+                excess.checked_sub(1)
+            })
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Slots;
+
+    fn hold(slots: &Slots) -> tokio::sync::OwnedSemaphorePermit {
+        slots.semaphore.clone().try_acquire_owned().unwrap()
+    }
+
+    #[test]
+    fn shrinking_takes_free_permits_at_once() {
+        let slots = Slots::new(3);
+
+        assert_eq!(slots.resize(1), 3);
+        assert_eq!(slots.size(), 1);
+        assert_eq!(slots.semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    fn shrinking_takes_held_permits_as_they_are_released() {
+        let slots = Slots::new(2);
+        let (first, second) = (hold(&slots), hold(&slots));
+
+        slots.resize(1);
+        assert_eq!(slots.semaphore.available_permits(), 0);
+
+        slots.release(first);
+        assert_eq!(slots.semaphore.available_permits(), 0);
+
+        slots.release(second);
+        assert_eq!(slots.semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    fn growing_cancels_a_pending_shrink_first() {
+        let slots = Slots::new(2);
+        let (first, second) = (hold(&slots), hold(&slots));
+
+        slots.resize(1);
+        slots.resize(3);
+        assert_eq!(slots.semaphore.available_permits(), 1);
+
+        slots.release(first);
+        slots.release(second);
+        assert_eq!(slots.semaphore.available_permits(), 3);
+    }
+
+    #[tokio::test]
+    async fn acquire_skips_permits_to_take_away() {
+        let slots = Slots::new(2);
+        let (first, second) = (hold(&slots), hold(&slots));
+
+        slots.resize(1);
+        drop(first);
+        drop(second);
+
+        let permit = slots.acquire().await;
+
+        assert!(permit.is_some());
+        assert_eq!(slots.semaphore.available_permits(), 0);
+
+        drop(permit);
+        assert_eq!(slots.semaphore.available_permits(), 1);
+    }
+}
@@ -0,0 +1,312 @@
+use crate::{backlog, get_pool, worker::ProcessTokens, DBType, Error, Worker, WorkerBuilder};
+use log::{error, info};
+use sqlx::AnyPool;
+use std::time::Duration;
+
+/// Runs a worker for each of several queues on one connection pool, sharing a
+/// concurrency budget between them according to their backlogs.
+#[derive(Clone)]
+pub struct Supervisor {
+    pool: AnyPool,
+    db_type: DBType,
+    workers: Vec<Worker>,
+    concurrency: u32,
+    balance_interval: Duration,
+    process: ProcessTokens,
+}
+
+impl Supervisor {
+    pub fn builder() -> SupervisorBuilder {
+        SupervisorBuilder::new()
+    }
+
+    /// Start every worker, blocks until all of them are stopped.
+    pub async fn start(&self) -> Result<(), Error> {
+        info!(
+            "Supervising the [{}] queues with {} concurrent jobs.",
+            self.workers
+                .iter()
+                .map(|worker| worker.queue())
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.concurrency
+        );
+
+        let handles = self
+            .workers
+            .iter()
+            .map(|worker| {
+                let worker = worker.clone();
+
+                tokio::spawn(async move { worker.start().await })
+            })
+            .collect::<Vec<_>>();
+
+        let balancer = {
+            let supervisor = self.clone();
+
+            tokio::spawn(async move { supervisor.balance_loop().await })
+        };
+
+        let mut result = Ok(());
+
+        for handle in handles {
+            match handle.await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    error!("Worker failed: {}", err);
+
+                    result = Err(err);
+                }
+                Err(err) => error!("Worker crashed: {}", err),
+            }
+        }
+
+        balancer.abort();
+
+        result
+    }
+
+    /// Ask every worker to stop once their running jobs are finished. Workers
+    /// stopping for the whole process, on Ctrl-C, a restart signal or the memory
+    /// limit, stop the others too.
+    pub fn shutdown(&self) {
+        info!("Supervisor shutdown requested, shutting down");
+
+        self.process.shutdown();
+    }
+
+    async fn balance_loop(&self) {
+        loop {
+            tokio::time::sleep(self.balance_interval).await;
+
+            if let Err(err) = self.balance().await {
+                error!("Failed to balance the workers: {}", err);
+            }
+        }
+    }
+
+    /// Share the concurrency budget between the workers still running, every
+    /// worker keeps at least one slot and the rest follows the backlogs.
+    async fn balance(&self) -> Result<(), Error> {
+        let workers = self
+            .workers
+            .iter()
+            .filter(|worker| !worker.is_stopping())
+            .collect::<Vec<_>>();
+
+        let mut backlogs = Vec::with_capacity(workers.len());
+
+        for worker in &workers {
+            backlogs.push(
+                backlog::measure(&self.pool, self.db_type, worker.queue())
+                    .await?
+                    .size,
+            );
+        }
+
+        for (worker, slots) in workers.iter().zip(allocate(self.concurrency, &backlogs)) {
+            if worker.concurrency() != slots {
+                worker.set_concurrency(slots)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn allocate(budget: u32, backlogs: &[u64]) -> Vec<u32> {
+    if backlogs.is_empty() {
+        return vec![];
+    }
+
+    let count = backlogs.len() as u32;
+    let spare = budget.saturating_sub(count);
+    let total = backlogs.iter().sum::<u64>();
+
+    if total == 0 {
+        return (0..count)
+            .map(|i| 1 + spare / count + u32::from(i < spare % count))
+            .collect();
+    }
+
+    let mut slots = backlogs
+        .iter()
+        .map(|backlog| 1 + (spare as u64 * backlog / total) as u32)
+        .collect::<Vec<_>>();
+
+    let mut largest = (0..backlogs.len()).collect::<Vec<_>>();
+    largest.sort_by_key(|&i| std::cmp::Reverse(backlogs[i]));
+
+    let assigned = slots.iter().sum::<u32>();
+
+    for &i in largest
+        .iter()
+        .cycle()
+        .take(budget.saturating_sub(assigned) as usize)
+    {
+        slots[i] += 1;
+    }
+
+    slots
+}
+
+#[derive(Clone)]
+pub struct SupervisorBuilder {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub queues: Vec<String>,
+    pub concurrency: u32,
+    pub balance_interval: Duration,
+    pub worker: WorkerBuilder,
+}
+
+impl Default for SupervisorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SupervisorBuilder {
+    pub fn new() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 0,
+            queues: vec!["default".to_string()],
+            concurrency: 1,
+            balance_interval: Duration::from_secs(3),
+            worker: WorkerBuilder::new(),
+        }
+    }
+
+    pub fn max_connections(mut self, max_connections: u32) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    pub fn min_connections(mut self, min_connections: u32) -> Self {
+        self.min_connections = min_connections;
+        self
+    }
+
+    pub fn queues(mut self, queues: &[&str]) -> Self {
+        self.queues = queues.iter().map(|queue| queue.to_string()).collect();
+        self
+    }
+
+    /// The number of jobs run at once across all the queues.
+    pub fn concurrency(mut self, concurrency: u32) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// How often the concurrency is shared again between the queues.
+    pub fn balance_interval(mut self, balance_interval: Duration) -> Self {
+        self.balance_interval = balance_interval;
+        self
+    }
+
+    /// The settings used for the worker of each queue, its queue, concurrency
+    /// and pool options are set by the supervisor.
+    pub fn worker(mut self, worker: WorkerBuilder) -> Self {
+        self.worker = worker;
+        self
+    }
+
+    pub async fn connect(self, database_url: &str) -> Result<Supervisor, Error> {
+        if self.queues.is_empty() {
+            return Err(Error::InvalidConfiguration(
+                "at least one queue is required".to_string(),
+            ));
+        }
+
+        if (self.concurrency as usize) < self.queues.len() {
+            return Err(Error::InvalidConfiguration(
+                "concurrency must be at least the number of queues".to_string(),
+            ));
+        }
+
+        if self.balance_interval.is_zero() {
+            return Err(Error::InvalidConfiguration(
+                "balance_interval must be non-zero".to_string(),
+            ));
+        }
+
+        if self.worker.autoscale.is_some() {
+            return Err(Error::InvalidConfiguration(
+                "autoscale can't be used with a supervisor, which balances the concurrency itself"
+                    .to_string(),
+            ));
+        }
+
+        self.worker.validate()?;
+
+        let (pool, db_type) = get_pool(
+            database_url,
+            crate::PoolOptions {
+                max_connections: self.max_connections,
+                min_connections: self.min_connections,
+            },
+        )
+        .await?;
+
+        let process = ProcessTokens::default();
+        let shares = allocate(self.concurrency, &vec![0; self.queues.len()]);
+        let mut workers = Vec::with_capacity(self.queues.len());
+
+        for (queue, worker_count) in self.queues.iter().zip(shares) {
+            let builder = WorkerBuilder {
+                queue: queue.clone(),
+                worker_count,
+                ..self.worker.clone()
+            };
+
+            workers.push(
+                builder
+                    .build(pool.clone(), db_type, process.clone())
+                    .await?,
+            );
+        }
+
+        Ok(Supervisor {
+            pool,
+            db_type,
+            workers,
+            concurrency: self.concurrency,
+            balance_interval: self.balance_interval,
+            process,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::allocate;
+
+    #[test]
+    fn allocate_shares_evenly_without_backlog() {
+        assert_eq!(allocate(10, &[0, 0, 0]), vec![4, 3, 3]);
+    }
+
+    #[test]
+    fn allocate_follows_a_dominant_backlog() {
+        let slots = allocate(10, &[1000, 1, 0]);
+
+        assert_eq!(slots.iter().sum::<u32>(), 10);
+        assert_eq!(slots, vec![8, 1, 1]);
+    }
+
+    #[test]
+    fn allocate_keeps_one_slot_per_queue() {
+        assert_eq!(allocate(3, &[0, 500, 20]), vec![1, 1, 1]);
+        assert_eq!(allocate(3, &[0, 0, 0]), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn allocate_hands_out_the_whole_budget() {
+        let slots = allocate(7, &[5, 5, 5]);
+
+        assert_eq!(slots.iter().sum::<u32>(), 7);
+        assert_eq!(slots, vec![3, 2, 2]);
+    }
+}
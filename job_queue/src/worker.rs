@@ -1,24 +1,116 @@
-use crate::{get_pool, models::Task, DBType, Error, Job};
+use crate::{
+    backlog::{self, Backlog},
+    get_pool, job,
+    locks::{self, Lock},
+    memory,
+    models::{unix_timestamp, JobOptions, Task},
+    rate_limit,
+    registry::{self, Registration},
+    slots::Slots,
+    Backoff, DBType, Error, Job, JobContext, JobMiddleware, Next,
+};
 use log::{error, info, warn};
-use sqlx::{Any, AnyPool, Connection};
+use sqlx::{Any, AnyConnection, AnyPool};
 use std::{
+    collections::{HashMap, VecDeque},
     future::Future,
     pin::Pin,
-    sync::Arc,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tokio::time::timeout;
+use tokio::{task::JoinHandle, time::timeout};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+type HookFuture = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+type WorkerHookFn = Arc<dyn Fn() -> HookFuture + Send + Sync>;
+type JobHookFn = Arc<dyn Fn(JobContext) -> HookFuture + Send + Sync>;
+type AfterJobFn = Arc<dyn Fn(JobContext, JobOutcome, Duration) -> HookFuture + Send + Sync>;
+type JobFailedFn = Arc<dyn Fn(JobContext, String) -> HookFuture + Send + Sync>;
+type JobRetryingFn = Arc<dyn Fn(JobContext, String, Duration) -> HookFuture + Send + Sync>;
+
+/// How a processed job ended, passed to [`WorkerBuilder::after_job`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JobOutcome {
+    Completed,
+    /// Cancelled through [`Client::cancel_job`](crate::Client::cancel_job).
+    Cancelled,
+    /// Stopped by a shutdown and released back to the queue without using an attempt.
+    Released,
+    /// Failed and released back to the queue for another attempt.
+    Retrying,
+    /// Failed for the last time and moved to the failed jobs.
+    Failed,
+}
+
+/// Seconds before a job released for being over a limit becomes available again.
+const LIMITED_RELEASE_DELAY: i64 = 1;
 
-type OnStoppingFn = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send + Sync>> + Send + Sync>;
+/// The tokens shared by every worker of a process, cancelled when a worker stops
+/// for a reason that concerns the whole process, such as its memory limit or a
+/// restart signal, so that a supervisor's other workers stop too.
+#[derive(Clone, Default)]
+pub(crate) struct ProcessTokens {
+    token: CancellationToken,
+    cancel_token: CancellationToken,
+}
+
+impl ProcessTokens {
+    pub(crate) fn shutdown(&self) {
+        self.token.cancel();
+    }
+}
+
+struct RunningJob {
+    token: CancellationToken,
+    cancelled: bool,
+}
 
 #[derive(Clone)]
 pub struct Worker {
+    id: String,
+    started_at: i64,
     db_type: DBType,
     pool: AnyPool,
     queue: String,
     retry_after: i64,
-    worker_count: u32,
-    on_stopping: Option<OnStoppingFn>,
+    heartbeat_interval: Duration,
+    heartbeat_timeout: Duration,
+    slots: Arc<Slots>,
+    autoscale: Option<(u32, u32)>,
+    autoscale_cooldown: Duration,
+    last_scaled: Arc<Mutex<Instant>>,
+    backoff: Backoff,
+    middleware: Vec<Arc<dyn JobMiddleware>>,
+    before_job: Option<JobHookFn>,
+    after_job: Option<AfterJobFn>,
+    on_job_failed: Option<JobFailedFn>,
+    on_job_retrying: Option<JobRetryingFn>,
+    on_idle: Option<WorkerHookFn>,
+    on_worker_started: Option<WorkerHookFn>,
+    on_stopping: Option<WorkerHookFn>,
+    shutdown_timeout: Option<Duration>,
+    /// Stops taking new jobs.
+    token: CancellationToken,
+    /// Cancels the contexts of running jobs, only on Ctrl-C or the memory limit.
+    cancel_token: CancellationToken,
+    process: ProcessTokens,
+    abort_token: CancellationToken,
+    running: Arc<Mutex<HashMap<i64, RunningJob>>>,
+    paused: Arc<AtomicBool>,
+    last_restart: Option<i64>,
+    max_jobs: Option<u64>,
+    max_time: Option<Duration>,
+    stop_when_empty: bool,
+    memory_limit: Option<u64>,
+    poll_interval: Duration,
+    max_poll_interval: Duration,
+    reserved_jobs: Arc<AtomicU64>,
+    prefetch: u32,
+    buffer: Arc<tokio::sync::Mutex<VecDeque<Task>>>,
 }
 
 impl Worker {
@@ -26,37 +118,859 @@ impl Worker {
         WorkerBuilder::new()
     }
 
-    async fn run(&self) -> Result<(), Error> {
-        let mut pool = self.pool.acquire().await?;
-        let mut conn = pool.begin().await?;
+    async fn run(&self) -> Result<bool, Error> {
+        match self.take().await? {
+            Some(task) => self.execute(task).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Take the next job to run, enforcing `max_jobs` and `stop_when_empty`.
+    async fn take(&self) -> Result<Option<Task>, Error> {
+        if let Some(max_jobs) = self.max_jobs {
+            if self.reserved_jobs.fetch_add(1, Ordering::SeqCst) >= max_jobs {
+                self.shutdown(&format!("Processed {} jobs", max_jobs));
+
+                return Ok(None);
+            }
+        }
+
+        let task = self.next_task().await;
+
+        if !matches!(task, Ok(Some(_))) {
+            if self.max_jobs.is_some() {
+                self.reserved_jobs.fetch_sub(1, Ordering::SeqCst);
+            }
+
+            if self.stop_when_empty && task.is_ok() {
+                self.shutdown("Queue is empty");
+            }
+        }
+
+        task
+    }
+
+    /// Process a reserved job, returns whether it ran rather than being released
+    /// back because of a limit, which then doesn't count towards `max_jobs`.
+    /// A job that couldn't be processed because of an error is released back too,
+    /// rather than staying reserved by this worker.
+    async fn execute(&self, task: Task) -> Result<bool, Error> {
+        let id = task.id;
+        let result = self.process(task).await;
+
+        if !matches!(result, Ok(Some(_))) && self.max_jobs.is_some() {
+            self.reserved_jobs.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        if result.is_err() {
+            if let Err(err) = self.release(id, 0, true).await {
+                error!("Failed to release job #{}: {}", id, err);
+            }
+        }
+
+        if let Some(memory_limit) = self.memory_limit {
+            if memory::resident_memory().is_some_and(|memory| memory > memory_limit) {
+                self.terminate(&format!("Memory limit of {} bytes exceeded", memory_limit));
+            }
+        }
+
+        result.map(|outcome| outcome.is_some())
+    }
+
+    /// Reserve jobs while there is capacity and run each of them in its own task,
+    /// until the worker shuts down. Returns the handles of the jobs still running.
+    async fn fetch(&self) -> Vec<JoinHandle<()>> {
+        let mut handles: Vec<JoinHandle<()>> = vec![];
+        let mut poll_interval = self.poll_interval;
+        let mut idle = false;
+
+        loop {
+            handles.retain(|handle| !handle.is_finished());
+
+            let permit = tokio::select! {
+                permit = self.slots.acquire() => match permit {
+                    Some(permit) => permit,
+                    None => break,
+                },
+                _ = self.token.cancelled() => break,
+            };
+
+            match self.take().await {
+                Ok(Some(task)) => {
+                    let worker = self.clone();
+
+                    handles.push(tokio::spawn(async move {
+                        if let Err(err) = worker.execute(task).await {
+                            error!("Failed to process job: {}", err);
+                        }
+
+                        worker.slots.release(permit);
+                    }));
+
+                    poll_interval = self.poll_interval;
+                    idle = false;
+
+                    continue;
+                }
+                Ok(None) => {
+                    if !idle {
+                        idle = true;
+
+                        if let Some(callback) = &self.on_idle {
+                            callback().await;
+                        }
+                    }
+                }
+                Err(err) => error!("Failed to fetch jobs: {}", err),
+            }
+
+            self.slots.release(permit);
+
+            tokio::select! {
+                _ = tokio::time::sleep(poll_interval) => {}
+                _ = self.token.cancelled() => {}
+            }
+
+            poll_interval = (poll_interval * 2).min(self.max_poll_interval);
+        }
+
+        handles
+    }
+
+    pub(crate) fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    pub(crate) fn is_stopping(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// The number of jobs the worker runs at once.
+    pub fn concurrency(&self) -> u32 {
+        self.slots.size()
+    }
+
+    /// Change the number of jobs the worker runs at once. Growing takes effect
+    /// immediately, shrinking as running jobs finish.
+    pub fn set_concurrency(&self, concurrency: u32) -> Result<(), Error> {
+        if concurrency == 0 {
+            return Err(Error::InvalidConfiguration(
+                "concurrency must be greater than zero".to_string(),
+            ));
+        }
+
+        let previous = self.slots.resize(concurrency);
+
+        if previous != concurrency {
+            info!(
+                "Concurrency of the [{}] queue changed from {} to {}",
+                self.queue, previous, concurrency
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Process the next available job, if any, then return whether one was processed.
+    /// A job released back to the queue because of a limit isn't counted.
+    pub async fn run_once(&self) -> Result<bool, Error> {
+        registry::register(&self.pool, self.db_type, &self.registration()).await?;
+
+        let monitor = {
+            let worker = self.clone();
+
+            tokio::spawn(async move { worker.monitor().await })
+        };
+
+        let result = self.run().await;
+
+        self.release_buffered().await;
+        monitor.abort();
+
+        if let Err(err) = registry::unregister(&self.pool, self.db_type, &self.id).await {
+            error!("Failed to unregister the worker: {}", err);
+        }
+
+        result
+    }
+
+    /// Stop taking new jobs and let `start` return once running jobs finish.
+    pub(crate) fn shutdown(&self, reason: &str) {
+        if !self.token.is_cancelled() {
+            info!("{}, shutting down", reason);
+
+            self.token.cancel();
+        }
+    }
+
+    /// Stop taking new jobs in every worker of the process.
+    fn shutdown_process(&self, reason: &str) {
+        self.shutdown(reason);
+        self.process.token.cancel();
+    }
+
+    /// Stop taking new jobs and cancel the contexts of the running ones, in every
+    /// worker of the process.
+    fn terminate(&self, reason: &str) {
+        if !self.cancel_token.is_cancelled() {
+            info!("{}, cancelling running jobs", reason);
+        }
+
+        self.shutdown_process(reason);
+        self.process.cancel_token.cancel();
+    }
+
+    async fn is_paused(&self) -> Result<bool, Error> {
+        let (count,) = sqlx::query_as::<Any, (i64,)>(&format!(
+            "SELECT COUNT(*) FROM paused_queues WHERE queue = {}",
+            match self.db_type {
+                DBType::Mysql => "?",
+                DBType::Postgres => "$1",
+            }
+        ))
+        .bind(&self.queue)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        let paused = count > 0;
+
+        if self.paused.swap(paused, Ordering::Relaxed) != paused {
+            if paused {
+                info!("Queue [{}] paused", self.queue);
+            } else {
+                info!("Queue [{}] resumed", self.queue);
+            }
+        }
+
+        Ok(paused)
+    }
+
+    /// Take the next job from the prefetch buffer, refilling it from the queue when empty.
+    async fn next_task(&self) -> Result<Option<Task>, Error> {
+        let mut buffer = self.buffer.lock().await;
+        let prefetched = !buffer.is_empty();
+
+        if !prefetched && !self.token.is_cancelled() && !self.is_paused().await? {
+            buffer.extend(self.reserve(self.prefetch).await?);
+        }
+
+        let Some(mut task) = buffer.pop_front() else {
+            return Ok(None);
+        };
+
+        // The job may have been cancelled while it waited in the buffer.
+        if prefetched {
+            match self.cancelled_at(task.id).await {
+                Ok(cancelled_at) => task.cancelled_at = cancelled_at,
+                Err(err) => {
+                    buffer.push_front(task);
+
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(Some(task))
+    }
+
+    async fn cancelled_at(&self, id: i64) -> Result<Option<i64>, Error> {
+        let cancelled_at = sqlx::query_as::<Any, (Option<i64>,)>(&format!(
+            "SELECT cancelled_at FROM jobs WHERE id = {}",
+            match self.db_type {
+                DBType::Mysql => "?",
+                DBType::Postgres => "$1",
+            }
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        Ok(cancelled_at.and_then(|(cancelled_at,)| cancelled_at))
+    }
+
+    /// Release the prefetched jobs that were never started.
+    async fn release_buffered(&self) {
+        let tasks = self.buffer.lock().await.drain(..).collect::<Vec<_>>();
+
+        for task in tasks {
+            if let Err(err) = self.release(task.id, 0, false).await {
+                error!("Failed to release job #{}: {}", task.id, err);
+            }
+        }
+    }
+
+    /// Reserve up to `limit` available jobs, committing the reservation so the
+    /// jobs can be cancelled or inspected while they run.
+    async fn reserve(&self, limit: u32) -> Result<Vec<Task>, Error> {
+        let mut conn = self.pool.begin().await?;
         let unix_timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map_err(|_| Error::Unknown)?
             .as_secs() as i64;
 
-        let task = sqlx::query_as::<Any, Task>(&format!(
+        let placeholder = |n: usize| match self.db_type {
+            DBType::Mysql => "?".to_string(),
+            DBType::Postgres => format!("${}", n),
+        };
+
+        let available = format!(
             r#"
             SELECT
-                id,
-                uuid,
-                payload,
-                attempts
+                id
             FROM
                 jobs
             WHERE
                 queue = {}
                 AND ((reserved_at IS NULL
                     AND available_at <= {})
-                    OR (reserved_at <= {}))
+                    OR (reserved_at IS NOT NULL
+                        AND heartbeat_at <= {})
+                    OR (heartbeat_at IS NULL
+                        AND reserved_at <= {}))
+                AND (group_key IS NULL
+                    OR NOT EXISTS (
+                        SELECT 1 FROM jobs AS earlier
+                        WHERE earlier.group_key = jobs.group_key AND earlier.id < jobs.id))
             ORDER BY
                 id ASC
-            LIMIT 1
+            LIMIT {}
             FOR UPDATE SKIP LOCKED"#,
+            placeholder(2),
+            placeholder(3),
+            placeholder(4),
+            placeholder(5),
+            limit,
+        );
+
+        let mut tasks = match self.db_type {
+            DBType::Postgres => {
+                sqlx::query_as::<Any, Task>(&format!(
+                    r#"
+                    UPDATE jobs
+                    SET reserved_at = {}, heartbeat_at = {}, reserved_by = $1, attempts = attempts + 1
+                    WHERE id IN ({})
+                    RETURNING id, uuid, payload, attempts, options, cancelled_at, backoff_ms
+                    "#,
+                    unix_timestamp, unix_timestamp, available,
+                ))
+                .bind(&self.id)
+                .bind(&self.queue)
+                .bind(unix_timestamp)
+                .bind(unix_timestamp - self.heartbeat_timeout.as_secs() as i64)
+                .bind(unix_timestamp - self.retry_after)
+                .fetch_all(&mut *conn)
+                .await
+                .map_err(Error::DatabaseError)?
+            }
+            DBType::Mysql => {
+                let ids = sqlx::query_as::<Any, (i64,)>(&available)
+                    .bind(&self.queue)
+                    .bind(unix_timestamp)
+                    .bind(unix_timestamp - self.heartbeat_timeout.as_secs() as i64)
+                    .bind(unix_timestamp - self.retry_after)
+                    .fetch_all(&mut *conn)
+                    .await
+                    .map_err(Error::DatabaseError)?
+                    .into_iter()
+                    .map(|(id,)| id.to_string())
+                    .collect::<Vec<_>>();
+
+                if ids.is_empty() {
+                    return Ok(vec![]);
+                }
+
+                sqlx::query(&format!(
+                    r#"
+                    UPDATE jobs
+                    SET reserved_at = {}, heartbeat_at = {}, reserved_by = ?, attempts = attempts + 1
+                    WHERE id IN ({})
+                    "#,
+                    unix_timestamp,
+                    unix_timestamp,
+                    ids.join(", "),
+                ))
+                .bind(&self.id)
+                .execute(&mut *conn)
+                .await
+                .map_err(Error::DatabaseError)?;
+
+                sqlx::query_as::<Any, Task>(&format!(
+                    r#"
+                    SELECT id, uuid, payload, attempts, options, cancelled_at, backoff_ms
+                    FROM jobs
+                    WHERE id IN ({})
+                    "#,
+                    ids.join(", "),
+                ))
+                .fetch_all(&mut *conn)
+                .await
+                .map_err(Error::DatabaseError)?
+            }
+        };
+
+        conn.commit().await?;
+
+        tasks.sort_by_key(|task| task.id);
+
+        Ok(tasks)
+    }
+
+    /// Process a reserved job, inside a span recording its outcome and duration
+    /// when the `tracing` feature is enabled.
+    async fn process(&self, task: Task) -> Result<Option<JobOutcome>, Error> {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+
+            let span = tracing::info_span!(
+                "job",
+                id = task.id,
+                uuid = %task.uuid,
+                job_type = tracing::field::Empty,
+                queue = %self.queue,
+                attempt = task.attempts,
+                trace_parent = tracing::field::Empty,
+                outcome = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+            );
+
+            let started = Instant::now();
+            let result = self.process_task(task).instrument(span.clone()).await;
+
+            match &result {
+                Ok(Some(outcome)) => span.record("outcome", tracing::field::debug(outcome)),
+                Ok(None) => span.record("outcome", "Limited"),
+                Err(_) => span.record("outcome", "Error"),
+            };
+            span.record("duration_ms", started.elapsed().as_millis() as u64);
+
+            result
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        self.process_task(task).await
+    }
+
+    /// Returns the outcome of the job, or `None` when it was released back to the
+    /// queue because of a limit.
+    async fn process_task(&self, task: Task) -> Result<Option<JobOutcome>, Error> {
+        let unix_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Error::Unknown)?
+            .as_secs() as i64;
+        // Taken from the payload so the hooks get it even if the job can't be decoded.
+        let job_type = task
+            .payload
+            .0
+            .get("type")
+            .and_then(|job_type| job_type.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let ctx = JobContext {
+            id: task.id,
+            uuid: task.uuid.clone(),
+            job_type,
+            queue: self.queue.clone(),
+            attempt: task.attempts as u32,
+            token: self.cancel_token.child_token(),
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("job_type", ctx.job_type.as_str());
+
+        let decoded = task.options().and_then(|options| {
+            serde_json::from_value::<Box<dyn Job>>(task.payload.0.clone())
+                .map(|job| (options, job))
+                .map_err(Error::SerdeError)
+        });
+
+        let (options, job) = match decoded {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                let error_message = match err {
+                    Error::SerdeError(err) => err.to_string(),
+                    err => err.to_string(),
+                };
+
+                error!(
+                    "Job {}#{} could not be decoded, moved to the failed jobs: {}",
+                    ctx.job_type, ctx.id, error_message
+                );
+
+                self.move_to_failed(
+                    ctx.id,
+                    &ctx.uuid,
+                    task.payload.0.to_string(),
+                    task.options.map(|options| options.0.to_string()),
+                    error_message.clone(),
+                )
+                .await?;
+
+                if let Some(callback) = &self.on_job_failed {
+                    callback(ctx.clone(), error_message).await;
+                }
+
+                if let Some(callback) = &self.after_job {
+                    callback(ctx, JobOutcome::Failed, Duration::ZERO).await;
+                }
+
+                return Ok(Some(JobOutcome::Failed));
+            }
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("trace_parent", options.trace_parent.as_deref());
+
+        // Cancelled and expired jobs go straight to the failed jobs, without
+        // waiting for or counting against any limit.
+        let skipped = match options.expires_at {
+            _ if task.cancelled_at.is_some() => Some(Error::JobCancelled),
+            Some(expires_at) if expires_at <= unix_timestamp => {
+                warn!("Job {}#{} expired", ctx.job_type, ctx.id);
+
+                Some(Error::JobExpired)
+            }
+            _ => job::validate_limits(job.as_ref()).err(),
+        };
+        let started_job = skipped.is_none();
+
+        let mut held = Vec::new();
+
+        if skipped.is_none() {
+            match self.acquire_limits(&ctx, job.as_ref(), &mut held).await {
+                Ok(None) => {}
+                Ok(Some((delay, reason))) => {
+                    self.release_limited(&ctx, &held, delay, &reason).await?;
+
+                    return Ok(None);
+                }
+                Err(err) => {
+                    self.release_locks(&ctx, &held).await;
+
+                    return Err(err);
+                }
+            }
+        }
+
+        self.running.lock().unwrap().insert(
+            ctx.id,
+            RunningJob {
+                token: ctx.token.clone(),
+                cancelled: task.cancelled_at.is_some(),
+            },
+        );
+
+        let started = Instant::now();
+        let result = match skipped {
+            Some(err) => Err(err),
+            None => {
+                info!("Job {}#{} started", ctx.job_type, ctx.id);
+
+                if let Some(callback) = &self.before_job {
+                    callback(ctx.clone()).await;
+                }
+
+                let abort_token = &self.abort_token;
+                let middleware = self
+                    .middleware
+                    .iter()
+                    .cloned()
+                    .chain(job.middleware())
+                    .collect::<Vec<_>>();
+                let result = std::panic::catch_unwind(|| {
+                    tokio::task::block_in_place(|| {
+                        tokio::runtime::Handle::current().block_on(async {
+                            tokio::select! {
+                                result = timeout(
+                                    options.timeout().unwrap_or_else(|| job.timeout()),
+                                    Next::new(job.as_ref(), &middleware).run(&ctx),
+                                ) => result.map_err(|_| Error::JobTimeout),
+                                _ = abort_token.cancelled() => Err(Error::JobAborted),
+                            }
+                        })
+                    })
+                });
+
+                match result {
+                    Ok(Ok(result)) => result,
+                    Ok(Err(err)) => Err(err),
+                    Err(_) => Err(Error::JobPanic),
+                }
+            }
+        };
+
+        let cancelled = self
+            .running
+            .lock()
+            .unwrap()
+            .remove(&ctx.id)
+            .is_some_and(|running| running.cancelled);
+
+        self.release_locks(&ctx, &held).await;
+
+        let duration = started.elapsed();
+
+        let outcome = match result {
+            Ok(_) => {
+                self.delete(ctx.id).await?;
+
+                info!("Job {}#{} finished", ctx.job_type, ctx.id);
+
+                JobOutcome::Completed
+            }
+            Err(_) if cancelled => {
+                self.cancel(&ctx, job.as_ref(), &options).await?;
+
+                info!("Job {}#{} cancelled", ctx.job_type, ctx.id);
+
+                JobOutcome::Cancelled
+            }
+            Err(Error::JobAborted) => {
+                self.release(ctx.id, 0, false).await?;
+
+                warn!(
+                    "Job {}#{} aborted during shutdown, released back to the queue",
+                    ctx.job_type, ctx.id
+                );
+
+                JobOutcome::Released
+            }
+            Err(_) if ctx.is_cancelled() => {
+                self.release(ctx.id, 0, false).await?;
+
+                info!(
+                    "Job {}#{} stopped during shutdown, released back to the queue",
+                    ctx.job_type, ctx.id
+                );
+
+                JobOutcome::Released
+            }
+            Err(err) => {
+                let error_message = err.to_string();
+                let _ = job.failed(err).await;
+
+                let tries = options.tries.unwrap_or_else(|| job.tries());
+                let attempts = ctx.attempt;
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|_| Error::Unknown)?
+                    .as_secs();
+
+                let backoff_delay = options
+                    .backoff
+                    .clone()
+                    .or_else(|| job.backoff())
+                    .unwrap_or_else(|| self.backoff.clone())
+                    .next_delay(
+                        attempts,
+                        task.backoff_ms
+                            .map(|backoff| Duration::from_millis(backoff as u64)),
+                    );
+                let backoff = backoff_delay.as_secs() as i64;
+
+                let retry = started_job
+                    && match options.retry_until {
+                        Some(retry_until) => time as i64 + backoff < retry_until,
+                        None => attempts < tries,
+                    };
+
+                if retry {
+                    error!(
+                        "Job {}#{} failed, will be retried in {} seconds",
+                        ctx.job_type, ctx.id, backoff
+                    );
+
+                    self.retry(ctx.id, backoff_delay).await?;
+
+                    if let Some(callback) = &self.on_job_retrying {
+                        callback(
+                            ctx.clone(),
+                            error_message,
+                            Duration::from_secs(backoff as u64),
+                        )
+                        .await;
+                    }
+
+                    JobOutcome::Retrying
+                } else {
+                    self.fail(&ctx, job.as_ref(), &options, error_message.clone())
+                        .await?;
+
+                    error!("Job {}#{} failed", ctx.job_type, ctx.id);
+
+                    if let Some(callback) = &self.on_job_failed {
+                        callback(ctx.clone(), error_message).await;
+                    }
+
+                    JobOutcome::Failed
+                }
+            }
+        };
+
+        if let Some(callback) = &self.after_job {
+            callback(ctx, outcome, duration).await;
+        }
+
+        Ok(Some(outcome))
+    }
+
+    /// Take the locks and rate limit hit the job needs to start, pushing the locks
+    /// to `held` as they are taken. Returns the delay and reason to release the
+    /// job back with when one of them isn't available.
+    async fn acquire_limits(
+        &self,
+        ctx: &JobContext,
+        job: &dyn Job,
+        held: &mut Vec<String>,
+    ) -> Result<Option<(i64, String)>, Error> {
+        if let Some(key) = job.overlap_key() {
+            match self.acquire_lock(ctx, [format!("overlap:{}", key)]).await? {
+                Some(lock) => held.push(lock),
+                None => {
+                    return Ok(Some((
+                        LIMITED_RELEASE_DELAY,
+                        "another job holds its overlap lock".to_string(),
+                    )))
+                }
+            }
+        }
+
+        if let Some(max_concurrency) = job.max_concurrency() {
+            let slots =
+                (0..max_concurrency).map(|slot| format!("concurrency:{}:{}", ctx.job_type, slot));
+
+            match self.acquire_lock(ctx, slots).await? {
+                Some(lock) => held.push(lock),
+                None => {
+                    return Ok(Some((
+                        LIMITED_RELEASE_DELAY,
+                        "it reached its concurrency limit".to_string(),
+                    )))
+                }
+            }
+        }
+
+        // Checked last so that jobs waiting for a lock don't use up the limit.
+        if let Some(limit) = job.rate_limit() {
+            if let Some(delay) = rate_limit::hit(&self.pool, self.db_type, &limit).await? {
+                return Ok(Some((
+                    delay,
+                    format!("it reached the [{}] rate limit", limit.key()),
+                )));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Take the first of the given locks that is free, if any.
+    async fn acquire_lock(
+        &self,
+        ctx: &JobContext,
+        names: impl IntoIterator<Item = String>,
+    ) -> Result<Option<String>, Error> {
+        for name in names {
+            let lock = Lock {
+                name: &name,
+                job_id: ctx.id,
+                owner: &self.id,
+                ttl: self.heartbeat_timeout,
+            };
+
+            if locks::acquire(&self.pool, self.db_type, &lock).await? {
+                return Ok(Some(name));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn release_locks(&self, ctx: &JobContext, names: &[String]) {
+        for name in names {
+            if let Err(err) = locks::release(&self.pool, self.db_type, name, ctx.id).await {
+                error!("Failed to release the lock {}: {}", name, err);
+            }
+        }
+    }
+
+    /// Give back a job that can't start yet because of a limit, along with the
+    /// locks it took so far.
+    async fn release_limited(
+        &self,
+        ctx: &JobContext,
+        locks: &[String],
+        delay: i64,
+        reason: &str,
+    ) -> Result<(), Error> {
+        self.release_locks(ctx, locks).await;
+        self.release(ctx.id, delay, false).await?;
+
+        info!(
+            "Job {}#{} released back to the queue for {} seconds, {}",
+            ctx.job_type, ctx.id, delay, reason
+        );
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: i64) -> Result<(), Error> {
+        let mut conn = self.pool.acquire().await?;
+
+        self.delete_reserved(&mut conn, id).await?;
+
+        Ok(())
+    }
+
+    /// Delete a job unless another worker took it over after this one stopped
+    /// sending its heartbeat, returns whether it was deleted.
+    async fn delete_reserved(&self, conn: &mut AnyConnection, id: i64) -> Result<bool, Error> {
+        let result = sqlx::query(&format!(
+            "DELETE FROM jobs WHERE id = {} AND reserved_by = {}",
+            match self.db_type {
+                DBType::Mysql => "?",
+                DBType::Postgres => "$1",
+            },
+            match self.db_type {
+                DBType::Mysql => "?",
+                DBType::Postgres => "$2",
+            }
+        ))
+        .bind(id)
+        .bind(&self.id)
+        .execute(conn)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        if result.rows_affected() == 0 {
+            warn!("Job #{} was taken over by another worker", id);
+        }
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Make a reserved job available again after `delay` seconds, optionally
+    /// giving back the attempt taken when it was reserved.
+    async fn release(&self, id: i64, delay: i64, count_attempt: bool) -> Result<(), Error> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Error::Unknown)?
+            .as_secs() as i64;
+
+        sqlx::query(&format!(
+            r#"
+            UPDATE jobs
+            SET reserved_at = NULL, heartbeat_at = NULL, reserved_by = NULL, available_at = {}, attempts = attempts - {}
+            WHERE id = {} AND reserved_by = {}
+            "#,
             if self.db_type == DBType::Mysql {
                 "?"
             } else {
                 "$1"
             },
+            if count_attempt { 0 } else { 1 },
             if self.db_type == DBType::Mysql {
                 "?"
             } else {
@@ -68,243 +982,396 @@ impl Worker {
                 "$3"
             },
         ))
-        .bind(&self.queue)
-        .bind(unix_timestamp)
-        .bind(unix_timestamp - self.retry_after)
-        .fetch_optional(&mut *conn)
+        .bind(time + delay)
+        .bind(id)
+        .bind(&self.id)
+        .execute(&self.pool)
         .await
         .map_err(Error::DatabaseError)?;
 
-        let task = match task {
-            Some(task) => task,
-            None => {
-                return {
-                    drop(conn);
-                    // conn.commit().await?;
-                    pool.close().await?;
-                    Ok(())
-                };
-            }
-        };
+        Ok(())
+    }
+
+    /// Release a failed job for another attempt after its backoff delay, which is
+    /// kept for the next one.
+    async fn retry(&self, id: i64, delay: Duration) -> Result<(), Error> {
+        let time = unix_timestamp(SystemTime::now())?;
 
         sqlx::query(&format!(
             r#"
             UPDATE jobs
-            SET reserved_at = {},  attempts = {}
-            WHERE id = {}
+            SET reserved_at = NULL, heartbeat_at = NULL, reserved_by = NULL, available_at = {}, backoff_ms = {}
+            WHERE id = {} AND reserved_by = {}
             "#,
-            unix_timestamp,
-            if self.db_type == DBType::Mysql {
-                "?"
-            } else {
-                "$1"
+            time + delay.as_secs() as i64,
+            delay.as_millis().min(i64::MAX as u128),
+            match self.db_type {
+                DBType::Mysql => "?",
+                DBType::Postgres => "$1",
             },
-            if self.db_type == DBType::Mysql {
-                "?"
-            } else {
-                "$2"
+            match self.db_type {
+                DBType::Mysql => "?",
+                DBType::Postgres => "$2",
             },
         ))
-        .bind(task.attempts + 1)
-        .bind(task.id)
+        .bind(id)
+        .bind(&self.id)
+        .execute(&self.pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        Ok(())
+    }
+
+    /// Move a cancelled job to the `cancelled_jobs` table, apart from the failed
+    /// jobs so that retrying those doesn't dispatch it again.
+    async fn cancel(
+        &self,
+        ctx: &JobContext,
+        job: &dyn Job,
+        options: &JobOptions,
+    ) -> Result<(), Error> {
+        let mut conn = self.pool.begin().await?;
+
+        if !self.delete_reserved(&mut conn, ctx.id).await? {
+            return Ok(());
+        }
+
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO cancelled_jobs (uuid, queue, payload, options)
+            VALUES {}
+            "#,
+            match self.db_type {
+                DBType::Mysql => "(?, ?, ?, ?)",
+                DBType::Postgres => "($1, $2, $3, $4)",
+            }
+        ))
+        .bind(&ctx.uuid)
+        .bind(&self.queue)
+        .bind(serde_json::to_string(job).map_err(Error::SerdeError)?)
+        .bind(serde_json::to_string(options).map_err(Error::SerdeError)?)
         .execute(&mut *conn)
         .await
         .map_err(Error::DatabaseError)?;
 
-        let job: Box<dyn Job> =
-            serde_json::from_value(task.payload.0).map_err(Error::SerdeError)?;
+        conn.commit().await?;
 
-        info!("Job {}#{} started", job.typetag_name(), task.id);
+        Ok(())
+    }
 
-        let result = std::panic::catch_unwind(|| {
-            tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async {
-                    timeout(Duration::from_secs(job.timeout() as u64), job.handle())
-                        .await
-                        .map_err(|_| Error::JobTimeout)
-                })
-            })
-        });
+    /// Move a reserved job to the failed jobs.
+    async fn fail(
+        &self,
+        ctx: &JobContext,
+        job: &dyn Job,
+        options: &JobOptions,
+        error_message: String,
+    ) -> Result<(), Error> {
+        self.move_to_failed(
+            ctx.id,
+            &ctx.uuid,
+            serde_json::to_string(job).map_err(Error::SerdeError)?,
+            Some(serde_json::to_string(options).map_err(Error::SerdeError)?),
+            error_message,
+        )
+        .await
+    }
 
-        let result = match result {
-            Ok(result) => result,
-            Err(_) => Err(Error::JobPanic),
-        };
+    /// Move a reserved job to the `failed_jobs` table, keeping its payload and
+    /// options as given so that jobs which can't be decoded are kept too.
+    async fn move_to_failed(
+        &self,
+        id: i64,
+        uuid: &str,
+        payload: String,
+        options: Option<String>,
+        error_message: String,
+    ) -> Result<(), Error> {
+        let mut conn = self.pool.begin().await?;
+
+        if !self.delete_reserved(&mut conn, id).await? {
+            return Ok(());
+        }
 
         sqlx::query(&format!(
-            "DELETE FROM jobs WHERE id = {}",
-            if self.db_type == DBType::Mysql {
-                "?"
-            } else {
-                "$1"
+            r#"
+            INSERT INTO failed_jobs (uuid, queue, payload, exception, options)
+            VALUES {}
+            "#,
+            match self.db_type {
+                DBType::Mysql => "(?, ?, ?, ?, ?)",
+                DBType::Postgres => "($1, $2, $3, $4, $5)",
             }
         ))
-        .bind(task.id)
+        .bind(uuid)
+        .bind(&self.queue)
+        .bind(payload)
+        .bind(error_message)
+        .bind(options)
         .execute(&mut *conn)
         .await
         .map_err(Error::DatabaseError)?;
 
-        match result {
-            Ok(Ok(_)) => {
-                info!("Job {}#{} finished", job.typetag_name(), task.id);
-            }
-            Ok(Err(err)) | Err(err) => {
-                let error_message = err.to_string();
-                let _ = job.failed(err).await;
+        conn.commit().await?;
 
-                let tries = job.tries();
-                let attempts = task.attempts;
+        Ok(())
+    }
 
-                if (attempts + 1) < tries {
-                    let time = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .map_err(|_| Error::Unknown)?
-                        .as_secs();
+    fn registration(&self) -> Registration<'_> {
+        Registration {
+            id: &self.id,
+            queues: std::slice::from_ref(&self.queue),
+            concurrency: self.concurrency(),
+            started_at: self.started_at,
+            timeout: self.heartbeat_timeout,
+        }
+    }
 
-                    let backoff = job.backoff((attempts + 1) as u32) as i64;
+    /// Refresh the heartbeat of the worker and its running jobs so they aren't considered
+    /// abandoned, and cancel the jobs flagged through [`Client::cancel_job`](crate::Client::cancel_job).
+    async fn monitor(&self) {
+        loop {
+            tokio::time::sleep(self.heartbeat_interval).await;
 
-                    error!(
-                        "Job {}#{} failed, will be retried in {} seconds",
-                        job.typetag_name(),
-                        task.id,
-                        backoff
-                    );
+            if let Err(err) =
+                registry::refresh(&self.pool, self.db_type, &self.registration()).await
+            {
+                error!("Failed to refresh the worker registration: {}", err);
+            }
 
-                    sqlx::query(&format!(
-                        r#"
-                        INSERT INTO jobs (uuid, queue, payload, attempts, available_at, created_at)
-                        VALUES ({}, {},{},{},{},{})
-                        "#,
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$1"
-                        },
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$2"
-                        },
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$3"
-                        },
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$4"
-                        },
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$5"
-                        },
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$6"
-                        },
-                    ))
-                    .bind(&task.uuid)
-                    .bind(&self.queue)
-                    .bind(serde_json::to_string(&job).map_err(Error::SerdeError)?)
-                    .bind(attempts + 1)
-                    .bind(time as i64 + backoff)
-                    .execute(&mut *conn)
-                    .await
-                    .map_err(Error::DatabaseError)?;
-                } else {
-                    sqlx::query(&format!(
-                        r#"
-                        INSERT INTO failed_jobs (uuid, queue, payload, exception)
-                        VALUES ({},{},{},{})
-                        "#,
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$1"
-                        },
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$2"
-                        },
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$3"
-                        },
-                        if self.db_type == DBType::Mysql {
-                            "?"
-                        } else {
-                            "$4"
-                        },
-                    ))
-                    .bind(&task.uuid)
-                    .bind(&self.queue)
-                    .bind(serde_json::to_string(&job).map_err(Error::SerdeError)?)
-                    .bind(error_message)
-                    .execute(&mut *conn)
-                    .await
-                    .map_err(Error::DatabaseError)?;
+            if let Err(err) = self.heartbeat().await {
+                error!("Failed to refresh the heartbeat of running jobs: {}", err);
+            }
+
+            if let Err(err) = self.check_cancellations().await {
+                error!("Failed to check for cancelled jobs: {}", err);
+            }
+
+            if let Err(err) = self.autoscale().await {
+                error!("Failed to autoscale the worker: {}", err);
+            }
 
-                    error!("Job {}#{} failed", job.typetag_name(), task.id);
+            match registry::last_restart(&self.pool, self.db_type).await {
+                Ok(last_restart) if last_restart > self.last_restart => {
+                    self.shutdown_process("Restart signal received");
                 }
+                Ok(_) => {}
+                Err(err) => error!("Failed to check for a restart signal: {}", err),
             }
         }
+    }
+
+    /// Grow the concurrency to cover the backlog once jobs have been waiting for
+    /// longer than `max_poll_interval`, or shrink it to the jobs in progress once
+    /// the backlog is empty, at most once per cooldown.
+    async fn autoscale(&self) -> Result<(), Error> {
+        let Some((min, max)) = self.autoscale else {
+            return Ok(());
+        };
+
+        if self.last_scaled.lock().unwrap().elapsed() < self.autoscale_cooldown {
+            return Ok(());
+        }
 
-        conn.commit().await?;
-        pool.close().await?;
+        let backlog = if self.paused.load(Ordering::Relaxed) {
+            Backlog::default()
+        } else {
+            backlog::measure(&self.pool, self.db_type, &self.queue).await?
+        };
+
+        let buffered = self.buffer.lock().await.len() as u64;
+        let busy = self.running.lock().unwrap().len() as u64 + buffered;
+        let current = self.concurrency();
+
+        let target = if backlog.size == 0 {
+            busy
+        } else if backlog.oldest_age >= self.max_poll_interval {
+            busy + backlog.size
+        } else {
+            current as u64
+        }
+        .clamp(min as u64, max as u64) as u32;
+
+        if target != current {
+            self.set_concurrency(target)?;
+
+            *self.last_scaled.lock().unwrap() = Instant::now();
+        }
 
         Ok(())
     }
 
-    pub async fn start(&self) -> Result<(), Error> {
-        info!("Processing jobs from the [{}] queue.", self.queue);
+    /// Refresh the heartbeat of the running and prefetched jobs of this worker, and
+    /// the locks held by the running ones.
+    async fn heartbeat(&self) -> Result<(), Error> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Error::Unknown)?
+            .as_secs() as i64;
 
-        let mut handles = vec![];
+        let running = self
+            .running
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        let buffered = self
+            .buffer
+            .lock()
+            .await
+            .iter()
+            .map(|task| task.id)
+            .collect::<Vec<_>>();
 
-        let token = tokio_util::sync::CancellationToken::new();
+        let ids = running
+            .iter()
+            .chain(&buffered)
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
 
-        for _ in 0..self.worker_count {
-            let worker = self.clone();
-            let cloned_token = token.clone();
+        if !ids.is_empty() {
+            sqlx::query(&format!(
+                "UPDATE jobs SET heartbeat_at = {} WHERE id IN ({}) AND reserved_by = {}",
+                time,
+                ids.join(", "),
+                match self.db_type {
+                    DBType::Mysql => "?",
+                    DBType::Postgres => "$1",
+                }
+            ))
+            .bind(&self.id)
+            .execute(&self.pool)
+            .await
+            .map_err(Error::DatabaseError)?;
+        }
+
+        locks::refresh(
+            &self.pool,
+            self.db_type,
+            &self.id,
+            &running,
+            self.heartbeat_timeout,
+        )
+        .await
+    }
 
-            let handle = tokio::spawn(async move {
-                let mut running = true;
+    async fn check_cancellations(&self) -> Result<(), Error> {
+        let ids = self
+            .running
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
 
-                while running {
-                    worker.run().await.unwrap();
+        if ids.is_empty() {
+            return Ok(());
+        }
 
-                    if cloned_token.is_cancelled() {
-                        running = false;
-                    }
+        let cancelled = sqlx::query_as::<Any, (i64,)>(&format!(
+            "SELECT id FROM jobs WHERE cancelled_at IS NOT NULL AND id IN ({})",
+            ids.join(", ")
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(Error::DatabaseError)?;
+
+        let mut running = self.running.lock().unwrap();
 
-                    tokio::time::sleep(Duration::from_millis(100)).await;
+        for (id,) in cancelled {
+            if let Some(job) = running.get_mut(&id) {
+                if !job.cancelled {
+                    info!("Job #{} cancelled, waiting for it to stop", id);
                 }
-            });
 
-            handles.push(handle);
+                job.cancelled = true;
+                job.token.cancel();
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn start(&self) -> Result<(), Error> {
+        info!("Processing jobs from the [{}] queue.", self.queue);
+
+        registry::register(&self.pool, self.db_type, &self.registration()).await?;
+
+        if let Some(callback) = &self.on_worker_started {
+            callback().await;
         }
 
-        tokio::select! {
+        let monitor = {
+            let worker = self.clone();
+
+            tokio::spawn(async move { worker.monitor().await })
+        };
+
+        let mut fetcher = {
+            let worker = self.clone();
+
+            tokio::spawn(async move { worker.fetch().await })
+        };
+
+        let fetched = tokio::select! {
             _ = tokio::signal::ctrl_c() => {
-                info!("Ctrl-C received, shutting down");
+                self.terminate("Ctrl-C received");
+
+                None
+            }
+            _ = async {
+                match self.max_time {
+                    Some(max_time) => tokio::time::sleep(max_time).await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                self.shutdown("Maximum run time reached");
 
-                token.cancel();
+                None
             }
-            _ = block_on_handles(&handles) => {}
+            _ = self.token.cancelled() => None,
+            fetched = &mut fetcher => Some(fetched),
+        };
+
+        let handles = match fetched {
+            Some(fetched) => fetched,
+            None => fetcher.await,
         }
+        .unwrap_or_else(|err| {
+            error!("Job fetcher crashed: {}", err);
+
+            vec![]
+        });
+
+        let deadline = self
+            .shutdown_timeout
+            .map(|shutdown_timeout| tokio::time::Instant::now() + shutdown_timeout);
 
         while !handles.iter().all(|handle| handle.is_finished()) {
             tokio::time::sleep(Duration::from_millis(300)).await;
 
+            if !self.abort_token.is_cancelled()
+                && deadline.is_some_and(|deadline| tokio::time::Instant::now() >= deadline)
+            {
+                warn!("Shutdown timeout reached, aborting running jobs");
+
+                self.abort_token.cancel();
+            }
+
             info!("Waiting for workers to finish");
         }
 
-        if token.is_cancelled() {
-            info!("All workers finished after Ctrl-C");
+        self.release_buffered().await;
+        monitor.abort();
+
+        if let Err(err) = registry::unregister(&self.pool, self.db_type, &self.id).await {
+            error!("Failed to unregister the worker: {}", err);
+        }
+
+        if self.token.is_cancelled() {
+            info!("All workers finished after shutdown was requested");
         } else {
             warn!("All workers finished, probably a crash");
         }
@@ -321,24 +1388,34 @@ impl Worker {
     }
 }
 
-async fn block_on_handles(handles: &[tokio::task::JoinHandle<()>]) {
-    loop {
-        if handles.iter().all(|handle| handle.is_finished()) {
-            break;
-        }
-
-        tokio::time::sleep(Duration::from_secs(1)).await;
-    }
-}
-
 #[derive(Default, Clone)]
 pub struct WorkerBuilder {
     pub max_connections: u32,
     pub min_connections: u32,
     pub worker_count: u32,
+    pub prefetch: u32,
     pub retry_after: i64,
+    pub heartbeat_interval: Duration,
+    pub heartbeat_timeout: Duration,
     pub queue: String,
-    pub on_stopping: Option<OnStoppingFn>,
+    pub backoff: Backoff,
+    pub shutdown_timeout: Option<Duration>,
+    pub max_jobs: Option<u64>,
+    pub max_time: Option<Duration>,
+    pub stop_when_empty: bool,
+    pub memory_limit: Option<u64>,
+    pub autoscale: Option<(u32, u32)>,
+    pub autoscale_cooldown: Duration,
+    pub poll_interval: Duration,
+    pub max_poll_interval: Duration,
+    pub middleware: Vec<Arc<dyn JobMiddleware>>,
+    pub before_job: Option<JobHookFn>,
+    pub after_job: Option<AfterJobFn>,
+    pub on_job_failed: Option<JobFailedFn>,
+    pub on_job_retrying: Option<JobRetryingFn>,
+    pub on_idle: Option<WorkerHookFn>,
+    pub on_worker_started: Option<WorkerHookFn>,
+    pub on_stopping: Option<WorkerHookFn>,
 }
 
 impl WorkerBuilder {
@@ -348,7 +1425,27 @@ impl WorkerBuilder {
             max_connections: 10,
             min_connections: 0,
             retry_after: 300,
+            heartbeat_interval: Duration::from_secs(2),
+            heartbeat_timeout: Duration::from_secs(30),
             worker_count: 1,
+            prefetch: 1,
+            backoff: Backoff::default(),
+            shutdown_timeout: None,
+            max_jobs: None,
+            max_time: None,
+            stop_when_empty: false,
+            memory_limit: None,
+            autoscale: None,
+            autoscale_cooldown: Duration::from_secs(30),
+            poll_interval: Duration::from_millis(100),
+            max_poll_interval: Duration::from_secs(3),
+            middleware: Vec::new(),
+            before_job: None,
+            after_job: None,
+            on_job_failed: None,
+            on_job_retrying: None,
+            on_idle: None,
+            on_worker_started: None,
             on_stopping: None,
         }
     }
@@ -363,34 +1460,198 @@ impl WorkerBuilder {
         self
     }
 
+    /// The number of jobs run at once, see [`Worker::set_concurrency`].
     pub fn worker_count(mut self, worker_count: u32) -> Self {
         self.worker_count = worker_count;
         self
     }
 
+    /// How many jobs to reserve in a single query, the ones that aren't started
+    /// yet are kept in memory and released back to the queue on shutdown.
+    pub fn prefetch(mut self, prefetch: u32) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    /// Seconds after which a job reserved without a heartbeat, e.g. by an older
+    /// version of the crate, is considered abandoned.
     pub fn retry_after(mut self, retry_after: i64) -> Self {
         self.retry_after = retry_after;
         self
     }
 
+    /// How often the heartbeat of running jobs is refreshed.
+    pub fn heartbeat_interval(mut self, heartbeat_interval: Duration) -> Self {
+        self.heartbeat_interval = heartbeat_interval;
+        self
+    }
+
+    /// How long after its last heartbeat a reserved job is considered abandoned
+    /// and made available to other workers.
+    pub fn heartbeat_timeout(mut self, heartbeat_timeout: Duration) -> Self {
+        self.heartbeat_timeout = heartbeat_timeout;
+        self
+    }
+
     pub fn queue(mut self, queue: &str) -> Self {
         self.queue = queue.to_string();
         self
     }
 
+    /// The backoff used for jobs that don't define their own.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// How long to wait for running jobs after a shutdown is requested, jobs still
+    /// running after that are aborted and released back to the queue.
+    pub fn shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(shutdown_timeout);
+        self
+    }
+
+    /// How long to wait before polling again once the queue is empty. The wait
+    /// doubles while the queue stays empty, up to `max_poll_interval`, and jobs
+    /// are fetched back to back as long as there are some available.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn max_poll_interval(mut self, max_poll_interval: Duration) -> Self {
+        self.max_poll_interval = max_poll_interval;
+        self
+    }
+
+    /// Stop the worker after processing this many jobs.
+    pub fn max_jobs(mut self, max_jobs: u64) -> Self {
+        self.max_jobs = Some(max_jobs);
+        self
+    }
+
+    /// Stop the worker after running for this long.
+    pub fn max_time(mut self, max_time: Duration) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
+    /// Stop the worker once the queue has no available jobs.
+    pub fn stop_when_empty(mut self) -> Self {
+        self.stop_when_empty = true;
+        self
+    }
+
+    /// Stop the worker once the process uses more than this many bytes of resident
+    /// memory, checked between jobs. Only supported on Linux.
+    pub fn memory_limit(mut self, memory_limit: u64) -> Self {
+        self.memory_limit = Some(memory_limit);
+        self
+    }
+
+    /// Adjust the concurrency between `min` and `max` from the backlog of the queue,
+    /// starting from `worker_count` clamped to that range.
+    pub fn autoscale(mut self, min: u32, max: u32) -> Self {
+        self.autoscale = Some((min, max));
+        self
+    }
+
+    /// The minimum time between two autoscaling adjustments.
+    pub fn autoscale_cooldown(mut self, autoscale_cooldown: Duration) -> Self {
+        self.autoscale_cooldown = autoscale_cooldown;
+        self
+    }
+
+    /// Wrap the handling of every job, in the order the middleware are added and
+    /// before the ones returned by [`Job::middleware`].
+    pub fn middleware(mut self, middleware: impl JobMiddleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Called when a job starts.
+    pub fn before_job<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(JobContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + Sync + 'static,
+    {
+        self.before_job = Some(Arc::new(move |ctx| -> HookFuture {
+            Box::pin(callback(ctx))
+        }));
+        self
+    }
+
+    /// Called once a reserved job is processed, with how long it ran.
+    pub fn after_job<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(JobContext, JobOutcome, Duration) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + Sync + 'static,
+    {
+        self.after_job = Some(Arc::new(move |ctx, outcome, duration| -> HookFuture {
+            Box::pin(callback(ctx, outcome, duration))
+        }));
+        self
+    }
+
+    /// Called with the error message once a job has failed for the last time and
+    /// was moved to the failed jobs.
+    pub fn on_job_failed<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(JobContext, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + Sync + 'static,
+    {
+        self.on_job_failed = Some(Arc::new(move |ctx, error| -> HookFuture {
+            Box::pin(callback(ctx, error))
+        }));
+        self
+    }
+
+    /// Called with the error message and the backoff delay when a failed job will
+    /// be retried.
+    pub fn on_job_retrying<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(JobContext, String, Duration) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + Sync + 'static,
+    {
+        self.on_job_retrying = Some(Arc::new(move |ctx, error, delay| -> HookFuture {
+            Box::pin(callback(ctx, error, delay))
+        }));
+        self
+    }
+
+    /// Called when the worker finds no job to run, once until it finds one again.
+    pub fn on_idle<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + Sync + 'static,
+    {
+        self.on_idle = Some(Arc::new(move || -> HookFuture { Box::pin(callback()) }));
+        self
+    }
+
+    /// Called once [`Worker::start`] has registered the worker.
+    pub fn on_worker_started<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + Sync + 'static,
+    {
+        self.on_worker_started = Some(Arc::new(move || -> HookFuture { Box::pin(callback()) }));
+        self
+    }
+
     pub fn on_stopping<F, Fut>(mut self, callback: F) -> Self
     where
         F: Fn() -> Fut + Send + Sync + 'static,
         Fut: Future<Output = ()> + Send + Sync + 'static,
     {
-        let callback_arc = Arc::new(move || -> Pin<Box<dyn Future<Output = ()> + Send + Sync>> {
-            Box::pin(callback())
-        });
+        let callback_arc = Arc::new(move || -> HookFuture { Box::pin(callback()) });
         self.on_stopping = Some(callback_arc);
         self
     }
 
     pub async fn connect(self, database_url: &str) -> Result<Worker, Error> {
+        self.validate()?;
+
         let (pool, db_type) = get_pool(
             database_url,
             crate::PoolOptions {
@@ -400,15 +1661,152 @@ impl WorkerBuilder {
         )
         .await?;
 
+        self.build(pool, db_type, ProcessTokens::default()).await
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.worker_count == 0 {
+            return Err(Error::InvalidConfiguration(
+                "worker_count must be at least 1".to_string(),
+            ));
+        }
+
+        if let Some((min, max)) = self.autoscale {
+            if min == 0 || max < min {
+                return Err(Error::InvalidConfiguration(
+                    "autoscale requires 1 <= min <= max".to_string(),
+                ));
+            }
+        }
+
+        if self.retry_after <= 0 {
+            return Err(Error::InvalidConfiguration(
+                "retry_after must be a positive number of seconds".to_string(),
+            ));
+        }
+
+        if self.prefetch == 0 {
+            return Err(Error::InvalidConfiguration(
+                "prefetch must be at least 1".to_string(),
+            ));
+        }
+
+        if self.max_jobs == Some(0) {
+            return Err(Error::InvalidConfiguration(
+                "max_jobs must be at least 1".to_string(),
+            ));
+        }
+
+        if self.poll_interval.is_zero() || self.max_poll_interval < self.poll_interval {
+            return Err(Error::InvalidConfiguration(
+                "poll_interval must be non-zero and not greater than max_poll_interval".to_string(),
+            ));
+        }
+
+        if self.memory_limit.is_some() && memory::resident_memory().is_none() {
+            warn!("Memory usage can't be measured on this platform, memory_limit is ignored");
+        }
+
+        if self.heartbeat_interval.is_zero() || self.heartbeat_timeout <= self.heartbeat_interval {
+            return Err(Error::InvalidConfiguration(
+                "heartbeat_timeout must be greater than a non-zero heartbeat_interval".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Build a worker on an existing pool, the pool options of the builder are ignored.
+    pub(crate) async fn build(
+        self,
+        pool: AnyPool,
+        db_type: DBType,
+        process: ProcessTokens,
+    ) -> Result<Worker, Error> {
+        let last_restart = registry::last_restart(&pool, db_type).await?;
+
+        let concurrency = match self.autoscale {
+            Some((min, max)) => self.worker_count.clamp(min, max),
+            None => self.worker_count,
+        };
+
         let worker = Worker {
+            id: Uuid::new_v4().to_string(),
+            started_at: unix_timestamp(SystemTime::now())?,
             db_type,
             pool,
             queue: self.queue,
             retry_after: self.retry_after,
-            worker_count: self.worker_count,
+            heartbeat_interval: self.heartbeat_interval,
+            heartbeat_timeout: self.heartbeat_timeout,
+            slots: Arc::new(Slots::new(concurrency)),
+            autoscale: self.autoscale,
+            autoscale_cooldown: self.autoscale_cooldown,
+            last_scaled: Arc::new(Mutex::new(Instant::now())),
+            backoff: self.backoff,
+            middleware: self.middleware,
+            before_job: self.before_job,
+            after_job: self.after_job,
+            on_job_failed: self.on_job_failed,
+            on_job_retrying: self.on_job_retrying,
+            on_idle: self.on_idle,
+            on_worker_started: self.on_worker_started,
             on_stopping: self.on_stopping,
+            shutdown_timeout: self.shutdown_timeout,
+            token: process.token.child_token(),
+            cancel_token: process.cancel_token.child_token(),
+            process,
+            abort_token: CancellationToken::new(),
+            running: Arc::new(Mutex::new(HashMap::new())),
+            paused: Arc::new(AtomicBool::new(false)),
+            last_restart,
+            max_jobs: self.max_jobs,
+            max_time: self.max_time,
+            stop_when_empty: self.stop_when_empty,
+            memory_limit: self.memory_limit,
+            poll_interval: self.poll_interval,
+            max_poll_interval: self.max_poll_interval,
+            reserved_jobs: Arc::new(AtomicU64::new(0)),
+            prefetch: self.prefetch,
+            buffer: Arc::new(tokio::sync::Mutex::new(VecDeque::new())),
         };
 
         Ok(worker)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::WorkerBuilder;
+    use std::time::Duration;
+
+    #[test]
+    fn validate_accepts_the_defaults() {
+        assert!(WorkerBuilder::new().validate().is_ok());
+        assert!(WorkerBuilder::new().autoscale(1, 1).validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_empty_limits() {
+        assert!(WorkerBuilder::new().worker_count(0).validate().is_err());
+        assert!(WorkerBuilder::new().prefetch(0).validate().is_err());
+        assert!(WorkerBuilder::new().max_jobs(0).validate().is_err());
+        assert!(WorkerBuilder::new().retry_after(0).validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_inverted_ranges() {
+        assert!(WorkerBuilder::new().autoscale(0, 4).validate().is_err());
+        assert!(WorkerBuilder::new().autoscale(4, 2).validate().is_err());
+        assert!(WorkerBuilder::new()
+            .poll_interval(Duration::from_secs(10))
+            .max_poll_interval(Duration::from_secs(5))
+            .validate()
+            .is_err());
+        assert!(WorkerBuilder::new()
+            .heartbeat_interval(Duration::from_secs(30))
+            .heartbeat_timeout(Duration::from_secs(30))
+            .validate()
+            .is_err());
+    }
+}
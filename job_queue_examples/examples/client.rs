@@ -18,6 +18,7 @@ async fn main() -> Result<(), Error> {
                 &DispatchOptions {
                     queue: Some("default".to_string()),
                     delay: Some(Duration::from_secs(1)),
+                    ..Default::default()
                 },
             )
             .await?;
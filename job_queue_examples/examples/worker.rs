@@ -11,6 +11,7 @@ async fn main() -> Result<(), Error> {
     let worker = Worker::builder()
         .max_connections(worker_count * 2)
         .worker_count(worker_count)
+        .shutdown_timeout(tokio::time::Duration::from_secs(30))
         .on_stopping(|| async move {
             println!("Worker is stopping");
 
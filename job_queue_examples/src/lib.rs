@@ -1,4 +1,4 @@
-use job_queue::{async_trait, serde, typetag, Error, Job};
+use job_queue::{async_trait, serde, typetag, Error, Job, JobContext};
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 #[serde(crate = "job_queue::serde")]
@@ -9,7 +9,7 @@ pub struct HelloJob {
 #[async_trait::async_trait]
 #[typetag::serde]
 impl Job for HelloJob {
-    async fn handle(&self) -> Result<(), Error> {
+    async fn handle(&self, _ctx: &JobContext) -> Result<(), Error> {
         println!("{}", self.message);
 
         tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;